@@ -0,0 +1,1565 @@
+use std::{fmt, io};
+
+use calculator_cli::{
+    Backend, DecimalSettings, Engine, Error, ErrorKind, FloatMode, Function, History,
+    IntegerSettings, MAX_DECIMAL_PRECISION, MAX_FIXED_DECIMALS, Notation, NumberFormat, Operator,
+    ROUNDING_MODES, Radix, Unit, Value, WORD_SIZES, rounding_mode_name,
+};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    DefaultTerminal, Frame,
+    buffer::Buffer,
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph, StatefulWidget, Widget, Wrap},
+};
+
+use crate::line_editor::LineEditor;
+
+/// Stateful calculator application.
+///
+/// Inspired by the “deep module” principle from Ousterhout’s *A Philosophy of
+/// Software Design*, `App` keeps the interactive state (error handling and
+/// event-driven behavior) behind a single interface and delegates the
+/// calculator itself to [`Engine`], so the rest of the program interacts with
+/// a clear abstraction boundary.
+#[derive(Debug, Default, Clone)]
+pub struct App {
+    engine: Engine,
+    history: History,
+    /// Index into `history` while browsing it with the arrow keys.
+    history_selected: Option<usize>,
+    /// Variable name being typed after pressing S or V.
+    name_prompt: Option<(NamePrompt, String)>,
+    /// The whole expression as text while typing it after pressing ` or F2.
+    editor: Option<LineEditor>,
+    /// Why the typed expression was not accepted; cleared by the next edit.
+    editor_error: Option<String>,
+    /// Reverse Polish entry: Enter pushes onto the engine's stack and
+    /// operators act on it immediately.
+    rpn: bool,
+    /// The last failure. The expression it came from is kept for fixing.
+    error: Option<Error>,
+    exit: bool,
+}
+
+/// What happens with the name typed into the variable prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NamePrompt {
+    /// Store the displayed value under the name.
+    Store,
+    /// Use the variable as the next operand.
+    Use,
+}
+
+/// Stack levels shown in RPN mode; deeper values are counted instead.
+const STACK_ROWS: usize = 6;
+
+pub fn error_text(error: impl fmt::Display) -> String {
+    format!("Error {error}")
+}
+
+impl App {
+    pub fn new(engine: Engine, history: History) -> Self {
+        Self {
+            engine,
+            history,
+            ..Self::default()
+        }
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.exit {
+            terminal.draw(|frame| self.draw(frame))?;
+            self.handle_events()?;
+        }
+        Ok(())
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        frame.render_widget(self, frame.area());
+    }
+
+    fn handle_events(&mut self) -> io::Result<()> {
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => self.handle_key_events(key),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: KeyEvent) {
+        if let Some(error) = self.error {
+            match key.code {
+                KeyCode::Char('a') | KeyCode::Char('A') => self.all_clear(),
+                KeyCode::Char('q') => self.exit = true,
+                KeyCode::Backspace => {
+                    self.error = None;
+                    self.engine.backspace();
+                }
+                KeyCode::Esc => self.error = None,
+                KeyCode::Char('`') | KeyCode::F(2) => {
+                    self.error = None;
+                    self.open_editor(error.token);
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        if self.name_prompt.is_some() {
+            self.handle_name_prompt_key(key);
+            return;
+        }
+
+        if self.editor.is_some() {
+            self.handle_editor_key(key);
+            return;
+        }
+
+        if self.history_selected.is_some() {
+            match key.code {
+                KeyCode::Up => self.select_older(),
+                KeyCode::Down => self.select_newer(),
+                KeyCode::Enter => self.recall_selected(),
+                KeyCode::Esc => self.history_selected = None,
+                KeyCode::Char('q') => self.exit = true,
+                _ => {}
+            }
+            return;
+        }
+
+        let integer = self.engine.backend() == Backend::Integer;
+        let result = match key.code {
+            KeyCode::Char('q') => {
+                self.exit = true;
+                Ok(())
+            }
+            // hex digits take the shifted letters, so `a` still clears
+            KeyCode::Char(ch @ 'A'..='F')
+                if integer && self.engine.integer_settings().radix == Radix::Hex =>
+            {
+                self.engine.push_digit(ch);
+                Ok(())
+            }
+            KeyCode::Char('a') | KeyCode::Char('A') => {
+                self.all_clear();
+                Ok(())
+            }
+            KeyCode::Char('`') | KeyCode::F(2) => {
+                self.open_editor(None);
+                Ok(())
+            }
+            KeyCode::Char('p') => {
+                self.rpn = !self.rpn;
+                self.engine.clear();
+                Ok(())
+            }
+            KeyCode::Enter | KeyCode::Char('=') if self.rpn => self.engine.rpn_enter(),
+            KeyCode::Tab if self.rpn => self.engine.stack_swap(),
+            KeyCode::Delete if self.rpn => {
+                self.engine.stack_drop();
+                Ok(())
+            }
+            KeyCode::Backspace if self.rpn && self.engine.input().is_empty() => {
+                self.engine.stack_drop();
+                Ok(())
+            }
+            KeyCode::Char('y') if self.rpn => self.engine.stack_dup(),
+            KeyCode::Char('h') if self.rpn => self.engine.stack_roll(),
+            KeyCode::Char('~') | KeyCode::F(9) if self.rpn => {
+                self.engine.stack_negate();
+                Ok(())
+            }
+            // postfix entry needs no grouping
+            KeyCode::Char('(') | KeyCode::Char(')') if self.rpn => Ok(()),
+            KeyCode::Enter | KeyCode::Char('=') => self.evaluate(),
+            KeyCode::Up => {
+                self.select_older();
+                Ok(())
+            }
+            KeyCode::Char('b') => {
+                self.update_integer_settings(|settings| settings.radix = settings.radix.next());
+                Ok(())
+            }
+            KeyCode::Char('z') => {
+                self.update_integer_settings(|settings| {
+                    let index = WORD_SIZES.iter().position(|bits| *bits == settings.bits);
+                    settings.bits =
+                        WORD_SIZES[index.map_or(0, |index| (index + 1) % WORD_SIZES.len())];
+                });
+                Ok(())
+            }
+            KeyCode::Char('j') => {
+                self.update_integer_settings(|settings| settings.signed = !settings.signed);
+                Ok(())
+            }
+            KeyCode::Char('.') => {
+                self.engine.push_decimal_point();
+                Ok(())
+            }
+            KeyCode::Char('m') => self.engine.memory_add(),
+            KeyCode::Char('n') => self.engine.memory_subtract(),
+            KeyCode::Char('r') => {
+                self.engine.memory_recall();
+                Ok(())
+            }
+            KeyCode::Char('c') => {
+                self.engine.memory_clear();
+                Ok(())
+            }
+            KeyCode::Char('s') => {
+                self.name_prompt = Some((NamePrompt::Store, String::new()));
+                Ok(())
+            }
+            KeyCode::Char('v') => {
+                self.name_prompt = Some((NamePrompt::Use, String::new()));
+                Ok(())
+            }
+            KeyCode::Char('d') => {
+                self.engine.set_backend(self.engine.backend().next());
+                Ok(())
+            }
+            KeyCode::Char('f') => {
+                let style = self.engine.fraction_style().next();
+                self.engine.set_fraction_style(style);
+                Ok(())
+            }
+            KeyCode::Char('o') => {
+                self.cycle_rounding_mode();
+                Ok(())
+            }
+            KeyCode::Char('u') => {
+                self.engine.set_angle_unit(self.engine.angle_unit().next());
+                Ok(())
+            }
+            KeyCode::F(3) => {
+                self.engine.set_float_mode(self.engine.float_mode().next());
+                Ok(())
+            }
+            KeyCode::F(4) => {
+                self.update_number_format(|format| format.notation = format.notation.next());
+                Ok(())
+            }
+            KeyCode::Char(',') => {
+                self.update_number_format(|format| format.grouping = !format.grouping);
+                Ok(())
+            }
+            KeyCode::Char('[') => {
+                self.update_number_format(|format| {
+                    format.decimals = format.decimals.and_then(|places| places.checked_sub(1));
+                });
+                Ok(())
+            }
+            KeyCode::Char(']') => {
+                self.update_number_format(|format| {
+                    format.decimals = Some(
+                        format
+                            .decimals
+                            .map_or(0, |places| places + 1)
+                            .min(MAX_FIXED_DECIMALS),
+                    );
+                });
+                Ok(())
+            }
+            KeyCode::Char('<') if !integer => {
+                self.adjust_precision(-1);
+                Ok(())
+            }
+            KeyCode::Char('>') if !integer => {
+                self.adjust_precision(1);
+                Ok(())
+            }
+            KeyCode::Char('~') | KeyCode::F(9) => {
+                self.engine.toggle_sign();
+                Ok(())
+            }
+            KeyCode::Char('%') if !self.rpn => self.engine.push_percent(),
+            KeyCode::Char('(') => self.engine.open_paren(),
+            KeyCode::Char(')') => self.engine.close_paren(),
+            KeyCode::Backspace => {
+                self.engine.backspace();
+                Ok(())
+            }
+            KeyCode::Char(ch) if ch.is_ascii_digit() => {
+                self.engine.push_digit(ch);
+                Ok(())
+            }
+            code => self.push_operation(code, integer),
+        };
+
+        if let Err(message) = result {
+            self.set_error(message);
+        }
+    }
+
+    /// Enters the operator or function bound to `code`, if any, infix or
+    /// onto the stack depending on the mode.
+    fn push_operation(&mut self, code: KeyCode, integer: bool) -> Result<(), Error> {
+        if let Some(operator) = operator_key(code, integer) {
+            if self.rpn {
+                self.engine.rpn_operator(operator)
+            } else {
+                self.engine.push_operator(operator)
+            }
+        } else if let Some(function) = function_key(code) {
+            if self.rpn {
+                self.engine.rpn_function(function)
+            } else {
+                self.engine.push_function(function)
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    fn handle_name_prompt_key(&mut self, key: KeyEvent) {
+        let Some((prompt, name)) = &mut self.name_prompt else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Char(ch) if ch.is_alphanumeric() || ch == '_' => name.push(ch),
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Esc => self.name_prompt = None,
+            KeyCode::Enter => {
+                let (prompt, name) = (*prompt, std::mem::take(name));
+                self.name_prompt = None;
+                let result = match prompt {
+                    NamePrompt::Store => self.engine.store_variable(&name),
+                    NamePrompt::Use if self.rpn => match Function::from_name(&name) {
+                        Some(function) => self.engine.rpn_function(function),
+                        None => self.engine.rpn_push_variable(&name),
+                    },
+                    NamePrompt::Use => {
+                        match (Function::from_name(&name), Operator::from_word(&name)) {
+                            (Some(function), _) => self.engine.push_function(function),
+                            (_, Some(operator)) => self.engine.push_operator(operator),
+                            _ => self.engine.push_variable(&name),
+                        }
+                    }
+                };
+                if let Err(message) = result {
+                    self.set_error(message);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Starts typing the pending calculation as text, with the cursor on
+    /// token `at` or else at the end.
+    fn open_editor(&mut self, at: Option<usize>) {
+        let text = self.engine.expression_text();
+        let cursor = at
+            .and_then(|index| self.engine.expression_token_range(index))
+            .map_or(text.chars().count(), |range| {
+                text[..range.start].chars().count()
+            });
+        let mut editor = LineEditor::new(text);
+        editor.set_cursor(cursor);
+        self.editor = Some(editor);
+    }
+
+    fn handle_editor_key(&mut self, key: KeyEvent) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let word = ctrl || key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Enter => {
+                let text = editor.text().to_string();
+                match self.evaluate_typed(&text) {
+                    Ok(()) => self.editor = None,
+                    Err(message) => {
+                        self.engine.clear();
+                        self.editor_error = Some(error_text(message));
+                    }
+                }
+                return;
+            }
+            KeyCode::Esc => self.editor = None,
+            KeyCode::Left if word => editor.word_left(),
+            KeyCode::Right if word => editor.word_right(),
+            KeyCode::Left => editor.move_left(),
+            KeyCode::Right => editor.move_right(),
+            KeyCode::Home => editor.home(),
+            KeyCode::End => editor.end(),
+            KeyCode::Backspace if word => editor.delete_word(),
+            KeyCode::Char('w') if ctrl => editor.delete_word(),
+            KeyCode::Backspace => editor.backspace(),
+            KeyCode::Delete => editor.delete(),
+            KeyCode::Char(ch) if !ctrl => editor.insert(ch),
+            _ => return,
+        }
+        self.editor_error = None;
+    }
+
+    /// Evaluates a typed expression as if it had been entered key by key.
+    /// `name = expression` also stores the result in variable `name`. In
+    /// RPN mode the result is pushed onto the stack.
+    fn evaluate_typed(&mut self, text: &str) -> Result<(), Error> {
+        let (name, expression) = self.engine.split_assignment(text)?;
+        self.engine.set_expression(expression)?;
+        self.evaluate()?;
+        if let Some(name) = name {
+            if !self.engine.just_evaluated() {
+                return Err(ErrorKind::IncompleteExpression.into());
+            }
+            self.engine.store_variable(name)?;
+        }
+        if self.rpn {
+            if !self.engine.just_evaluated() && !self.engine.expression_line().is_empty() {
+                return Err(ErrorKind::IncompleteExpression.into());
+            }
+            self.engine.rpn_enter()?;
+        }
+        Ok(())
+    }
+
+    fn cycle_rounding_mode(&mut self) {
+        let settings = self.engine.decimal_settings();
+        let index = ROUNDING_MODES
+            .iter()
+            .position(|mode| *mode == settings.rounding)
+            .map_or(0, |index| (index + 1) % ROUNDING_MODES.len());
+        self.engine.set_decimal_settings(DecimalSettings {
+            rounding: ROUNDING_MODES[index],
+            ..settings
+        });
+    }
+
+    fn update_integer_settings(&mut self, update: impl FnOnce(&mut IntegerSettings)) {
+        let mut settings = self.engine.integer_settings();
+        update(&mut settings);
+        self.engine.set_integer_settings(settings);
+    }
+
+    /// Changes the number format. `[` and `]` step the fixed decimals
+    /// through every digit, then 0, 1, 2… places.
+    fn update_number_format(&mut self, change: impl FnOnce(&mut NumberFormat)) {
+        let mut format = self.engine.number_format();
+        change(&mut format);
+        self.engine.set_number_format(format);
+    }
+
+    fn adjust_precision(&mut self, delta: i64) {
+        let settings = self.engine.decimal_settings();
+        let precision = settings
+            .precision
+            .saturating_add_signed(delta)
+            .clamp(1, MAX_DECIMAL_PRECISION);
+        self.engine.set_decimal_settings(DecimalSettings {
+            precision,
+            ..settings
+        });
+    }
+
+    /// Title of the Result block, naming the active arithmetic backend.
+    fn result_title(&self) -> String {
+        let numbers = match self.engine.backend() {
+            Backend::Float => match self.engine.float_mode() {
+                FloatMode::Checked => "f64".into(),
+                FloatMode::Ieee => "f64 IEEE".into(),
+            },
+            Backend::Decimal => {
+                let settings = self.engine.decimal_settings();
+                format!(
+                    "decimal {} digits, {}",
+                    settings.precision,
+                    rounding_mode_name(settings.rounding)
+                )
+            }
+            Backend::Rational => format!("rational ({})", self.engine.fraction_style().name()),
+            Backend::Integer => format!("integer {}", self.engine.integer_settings().word_name()),
+        };
+        let mut title = format!("Result · {numbers} · {}", self.engine.angle_unit().name());
+        let format = self.engine.number_format();
+        if format.notation != Notation::Plain {
+            title.push_str(&format!(" · {}", format.notation.name()));
+        }
+        if let Some(places) = format.decimals {
+            title.push_str(&format!(" · {places} dp"));
+        }
+        if format.grouping {
+            title.push_str(" · 1,000s");
+        }
+        if self.engine.wrapped() {
+            title.push_str(" · wrapped");
+        }
+        // converted money is only as good as the rates behind it
+        if self.engine.result_unit().is_some_and(Unit::is_money) {
+            match self.engine.rates().as_of() {
+                Some(date) => title.push_str(&format!(" · rates as of {date}")),
+                None => title.push_str(" · rates undated"),
+            }
+        }
+        title
+    }
+
+    /// Lines of the Result block: the displayed value, or in the integer
+    /// backend the value in every radix with the active one in bold.
+    fn result_lines(&self) -> Vec<Line<'static>> {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let integer = match self.engine.display_number() {
+            Ok(Value::Integer(value)) if self.error.is_none() => value,
+            _ => return vec![Line::from(Span::styled(self.display_value(), bold))],
+        };
+
+        let settings = self.engine.integer_settings();
+        let (value, _) = settings.wrap(integer);
+        Radix::ALL
+            .into_iter()
+            .map(|radix| {
+                let text = format!(
+                    "{} {}",
+                    radix.name().to_uppercase(),
+                    settings.format(value, radix)
+                );
+                if radix == settings.radix {
+                    Line::from(Span::styled(text, bold))
+                } else {
+                    Line::from(text)
+                }
+            })
+            .collect()
+    }
+
+    fn evaluate(&mut self) -> Result<(), Error> {
+        // re-evaluating a shown result or a lone number is not a new
+        // calculation, unless it repeats the last operation
+        let repeat = self.engine.repeat_line();
+        let fresh =
+            repeat.is_some() || !self.engine.just_evaluated() && !self.engine.is_lone_operand();
+        let expression = repeat.unwrap_or_else(|| self.engine.expression_line());
+        self.engine.evaluate()?;
+        if fresh && self.engine.just_evaluated() {
+            self.history.push(expression, self.engine.result_text());
+        }
+        Ok(())
+    }
+
+    fn select_older(&mut self) {
+        self.history_selected = match self.history_selected {
+            Some(index) => Some(index.saturating_sub(1)),
+            None => self.history.len().checked_sub(1),
+        };
+    }
+
+    fn select_newer(&mut self) {
+        self.history_selected = self
+            .history_selected
+            .map(|index| index + 1)
+            .filter(|&index| index < self.history.len());
+    }
+
+    fn recall_selected(&mut self) {
+        if let Some(entry) = self
+            .history_selected
+            .and_then(|index| self.history.get(index))
+        {
+            self.engine.recall(&entry.result);
+        }
+        self.history_selected = None;
+    }
+
+    fn all_clear(&mut self) {
+        self.engine.clear();
+        self.engine.stack_clear();
+        self.error = None;
+    }
+
+    /// Shows `error`, keeping the expression so that Backspace can fix it.
+    fn set_error(&mut self, error: Error) {
+        self.error = Some(error);
+    }
+
+    /// The Result value, with the unit of a quantity next to it.
+    fn display_value(&self) -> String {
+        if let Some(error) = self.error {
+            return error_text(error);
+        }
+        match self.engine.result_unit() {
+            Some(unit) => format!("{} {unit}", self.engine.display()),
+            None => self.engine.display(),
+        }
+    }
+
+    fn expression_line(&self) -> String {
+        match &self.name_prompt {
+            Some((NamePrompt::Store, name)) => return format!("Store as: {name}_"),
+            Some((NamePrompt::Use, name)) => return format!("Use variable: {name}_"),
+            None => {}
+        }
+
+        let line = self.engine.expression_line();
+        if line.is_empty() {
+            "Enter digits and choose an operator".into()
+        } else {
+            line
+        }
+    }
+}
+
+/// The typed text with the cursor shown as a reversed cell.
+fn editor_line(editor: &LineEditor) -> Line<'static> {
+    let mut chars = editor.text().chars();
+    let before: String = chars.by_ref().take(editor.cursor()).collect();
+    let under = chars.next().unwrap_or(' ');
+    let after: String = chars.collect();
+    Line::from(vec![
+        Span::raw(before),
+        Span::styled(
+            under.to_string(),
+            Style::default().add_modifier(Modifier::REVERSED),
+        ),
+        Span::raw(after),
+    ])
+}
+
+/// Lays out key bindings in lines of at most `width` columns, separated by
+/// `·`, with the first one in bold.
+fn wrap_instructions(keys: &[&'static str], width: u16) -> Vec<Line<'static>> {
+    const SEPARATOR: &str = " · ";
+    let width = usize::from(width);
+    let mut lines: Vec<Line> = Vec::new();
+    for (index, key) in keys.iter().enumerate() {
+        let span = if index == 0 {
+            Span::styled(*key, Style::default().add_modifier(Modifier::BOLD))
+        } else {
+            Span::raw(*key)
+        };
+        match lines.last_mut() {
+            Some(line) if line.width() + SEPARATOR.chars().count() + span.width() <= width => {
+                line.push_span(SEPARATOR);
+                line.push_span(span);
+            }
+            _ => lines.push(Line::from(span)),
+        }
+    }
+    lines
+}
+
+fn operator_key(code: KeyCode, integer: bool) -> Option<Operator> {
+    let KeyCode::Char(ch) = code else {
+        return None;
+    };
+    Some(match ch {
+        '+' => Operator::Add,
+        '-' => Operator::Subtract,
+        '*' | 'x' | 'X' => Operator::Multiply,
+        '/' | ':' => Operator::Divide,
+        '^' => Operator::Power,
+        '%' => Operator::Modulo,
+        '&' => Operator::And,
+        '|' => Operator::Or,
+        '#' => Operator::Xor,
+        '<' if integer => Operator::ShiftLeft,
+        '>' if integer => Operator::ShiftRight,
+        '{' => Operator::RotateLeft,
+        '}' => Operator::RotateRight,
+        _ => return None,
+    })
+}
+
+fn function_key(code: KeyCode) -> Option<Function> {
+    let KeyCode::Char(ch) = code else {
+        return None;
+    };
+    Some(match ch {
+        'i' => Function::Sin,
+        'k' => Function::Cos,
+        't' => Function::Tan,
+        'g' => Function::Log,
+        'l' => Function::Ln,
+        'w' => Function::Sqrt,
+        'e' => Function::Exp,
+        '!' => Function::Not,
+        _ => return None,
+    })
+}
+
+impl Widget for &App {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut Buffer) {
+        let [main, side] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(28)]).areas(area);
+        let registers = self.register_lines();
+        let [history, registers_area] = Layout::vertical([
+            Constraint::Min(0),
+            Constraint::Length(registers.len() as u16 + 2),
+        ])
+        .areas(side);
+        let result = self.result_lines();
+        let top = match &self.editor {
+            Some(editor) => vec![editor_line(editor)],
+            None if self.rpn => self.stack_lines(),
+            None => vec![self.highlighted_expression_line()],
+        };
+        let instructions = wrap_instructions(&self.instructions(), main.width.saturating_sub(2));
+        let layout = Layout::vertical([
+            Constraint::Length(top.len() as u16 + 2),
+            Constraint::Length(result.len() as u16 + 2),
+            // the key help gets what is left, cut short on a small screen
+            Constraint::Fill(1),
+        ])
+        .split(main);
+
+        let title = match &self.editor {
+            Some(_) => self
+                .editor_error
+                .clone()
+                .unwrap_or_else(|| "Type expression · Enter: evaluate · Esc: cancel".into()),
+            None if self.rpn => "Stack (RPN)".into(),
+            None => "Expression".into(),
+        };
+        let title = match self.error {
+            Some(_) if self.editor.is_none() => {
+                format!("{title} · A: clear · Backspace: fix · F2: edit")
+            }
+            _ => title,
+        };
+        let mut block = Block::bordered().title(title);
+        if let Some(preview) = self.preview() {
+            block = block.title_bottom(
+                Line::styled(
+                    format!(" = {preview} "),
+                    Style::default().add_modifier(Modifier::DIM),
+                )
+                .right_aligned(),
+            );
+        }
+        let expression = match &self.editor {
+            Some(editor) => {
+                // keep the cursor in view on long lines
+                let width = usize::from(main.width.saturating_sub(3));
+                let scroll = editor.cursor().saturating_sub(width);
+                Paragraph::new(top).block(block).scroll((0, scroll as u16))
+            }
+            None => Paragraph::new(top)
+                .block(block)
+                .alignment(ratatui::layout::Alignment::Right),
+        };
+
+        let value = Paragraph::new(result)
+            .alignment(ratatui::layout::Alignment::Right)
+            .block(Block::bordered().title(self.result_title()));
+
+        // a binding wider than the panel still wraps
+        let instruction = Paragraph::new(instructions)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered());
+
+        expression.render(layout[0], buf);
+        value.render(layout[1], buf);
+        instruction.render(layout[2], buf);
+        self.render_history(history, buf);
+        Paragraph::new(registers)
+            .block(Block::bordered().title("Registers"))
+            .render(registers_area, buf);
+    }
+}
+
+impl App {
+    /// The keys that do something right now: those of the line editor, the
+    /// history list, or RPN or infix entry, with the programmer keys only
+    /// in the integer backend.
+    fn instructions(&self) -> Vec<&'static str> {
+        if self.editor.is_some() {
+            return vec![
+                "←/→: move",
+                "Ctrl+←/→: by word",
+                "Home/End: start/end",
+                "Backspace/Del: delete",
+                "Ctrl+W: delete word",
+                "Enter: evaluate",
+                "Esc: cancel",
+            ];
+        }
+        if self.history_selected.is_some() {
+            return vec!["↑/↓: select", "Enter: recall", "Esc: back", "Q: quit"];
+        }
+
+        let integer = self.engine.backend() == Backend::Integer;
+        let mut keys = vec![
+            if integer && self.engine.integer_settings().radix == Radix::Hex {
+                "Digits 0-9 A-F"
+            } else {
+                "Digits 0-9"
+            },
+        ];
+        if self.rpn {
+            keys.extend([
+                "+ - * : ^ %",
+                "~: ±",
+                "Enter: push/dup",
+                "Tab: swap",
+                "Del: drop",
+                "Y: dup",
+                "H: roll",
+                "P: infix",
+            ]);
+        } else {
+            keys.extend([
+                "+ - * : ( ) ^ //",
+                "%: percent, or mod before a number",
+                "~: ±",
+                "Enter/=: evaluate",
+                "↑/↓: history",
+                "P: RPN",
+            ]);
+        }
+        keys.extend(["M/N: M+/M−", "R: MR", "C: MC"]);
+        keys.push(if self.rpn {
+            "S/V: store/use variable or Ans"
+        } else {
+            "S/V: store/use variable, Ans, unit, to, chg, markup or margin"
+        });
+        keys.extend([
+            "I/K/T: sin/cos/tan",
+            "G/L: log/ln",
+            "E: exp",
+            "W: √",
+            "D: f64/decimal/rational/integer",
+        ]);
+        if integer {
+            keys.extend([
+                "& | # ! < > { }: and or xor not shifts rotates",
+                "B: radix",
+                "Z: word size",
+                "J: signed",
+            ]);
+        } else {
+            keys.extend([
+                "F: fraction display",
+                "O: rounding",
+                "</>: precision",
+                "U: rad/deg/grad",
+                "F3: IEEE ∞/NaN",
+                "F4: plain/auto/sci/eng",
+                ",: 1,000s",
+                "[/]: decimal places",
+            ]);
+        }
+        keys.extend(["`/F2: type expression", "A: AC", "Q: quit"]);
+        keys
+    }
+
+    /// The expression line with the token an error is about in red.
+    fn highlighted_expression_line(&self) -> Line<'static> {
+        let line = self.expression_line();
+        let range = self
+            .error
+            .and_then(|error| error.token)
+            .filter(|_| self.name_prompt.is_none())
+            .and_then(|index| self.engine.expression_token_range(index));
+        let Some(range) = range else {
+            return Line::from(line);
+        };
+        Line::from(vec![
+            Span::raw(line[..range.start].to_string()),
+            Span::styled(
+                line[range.clone()].to_string(),
+                Style::default()
+                    .fg(Color::Red)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            ),
+            Span::raw(line[range.end..].to_string()),
+        ])
+    }
+
+    /// The running total of the expression being built, while there is one.
+    fn preview(&self) -> Option<String> {
+        if self.error.is_some() || self.name_prompt.is_some() {
+            return None;
+        }
+        match &self.editor {
+            Some(editor) => self.engine.preview_expression(editor.text()),
+            None if self.rpn => None,
+            None => self.engine.preview(),
+        }
+    }
+
+    /// The RPN stack, deepest shown level first so that level 1, the top,
+    /// sits at the bottom next to the number being typed.
+    fn stack_lines(&self) -> Vec<Line<'static>> {
+        if self.name_prompt.is_some() {
+            return vec![Line::from(self.expression_line())];
+        }
+
+        let stack = self.engine.stack();
+        let shown = stack.len().min(STACK_ROWS);
+        let mut lines = Vec::new();
+        if stack.len() > shown {
+            lines.push(Line::from(format!("… {} more", stack.len() - shown)));
+        }
+        for (level, value) in (1..=shown).rev().zip(&stack[stack.len() - shown..]) {
+            lines.push(Line::from(format!(
+                "{level}: {}",
+                self.engine.format_value(value)
+            )));
+        }
+        if !self.engine.input().is_empty() {
+            lines.push(Line::from(format!("{}_", self.engine.input())));
+        }
+        if lines.is_empty() {
+            lines.push(Line::from("Type a number and press Enter to push it"));
+        }
+        lines
+    }
+
+    /// One line per active register: memory first, then variables by name.
+    fn register_lines(&self) -> Vec<Line<'static>> {
+        let mut lines: Vec<Line> = self
+            .engine
+            .memory()
+            .map(|value| format!("M = {}", self.engine.format_value(value)))
+            .into_iter()
+            .chain(self.engine.variables().iter().map(|(name, quantity)| {
+                format!("{name} = {}", self.engine.format_quantity(quantity))
+            }))
+            .map(Line::from)
+            .collect();
+        if lines.is_empty() {
+            lines.push(Line::from("none"));
+        }
+        lines
+    }
+
+    fn render_history(&self, area: ratatui::prelude::Rect, buf: &mut Buffer) {
+        let items: Vec<ListItem> = self
+            .history
+            .entries()
+            .iter()
+            .map(|entry| {
+                let result = self.engine.format_text(&entry.result);
+                ListItem::new(format!("{} = {result}", entry.expression))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title("History"))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        // without a selection, keep the newest entries in view
+        let visible = usize::from(area.height.saturating_sub(2));
+        let mut state = ListState::default()
+            .with_offset(self.history.len().saturating_sub(visible))
+            .with_selected(self.history_selected);
+        StatefulWidget::render(list, area, buf, &mut state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calculator_cli::{AngleUnit, Rates, Value};
+    use crossterm::event::KeyModifiers;
+    use ratatui::{buffer::Buffer, layout::Rect};
+
+    fn press(app: &mut App, keys: &str) {
+        for ch in keys.chars() {
+            let code = if ch == '\n' {
+                KeyCode::Enter
+            } else {
+                KeyCode::Char(ch)
+            };
+            app.handle_key_events(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn keys_drive_the_engine() {
+        let mut app = App::default();
+        press(&mut app, "(2+3)x4");
+        assert_eq!(app.expression_line(), "(2 + 3) × 4");
+
+        press(&mut app, "\n");
+        assert_eq!(app.display_value(), "20");
+    }
+
+    #[test]
+    fn function_keys_apply_to_the_pending_number() {
+        let mut app = App::default();
+        press(&mut app, "16w");
+        assert_eq!(app.expression_line(), "sqrt(16)");
+        press(&mut app, "+vln\n1)\n");
+        assert_eq!(app.display_value(), "4");
+
+        press(&mut app, "a1-2=w=");
+        assert_eq!(
+            app.display_value(),
+            "Error square root of a negative number"
+        );
+    }
+
+    #[test]
+    fn divide_by_zero_sets_error() {
+        let mut app = App::default();
+        press(&mut app, "8/0=");
+
+        assert_eq!(app.display_value(), "Error Cannot divide by zero");
+        assert_eq!(app.expression_line(), "8 ÷ 0", "the expression is kept");
+
+        // digits and operators are ignored while an error is shown
+        press(&mut app, "5");
+        assert!(app.error.is_some());
+        press(&mut app, "a");
+        assert!(app.error.is_none());
+        assert_eq!(app.expression_line(), "Enter digits and choose an operator");
+    }
+
+    #[test]
+    fn errors_highlight_the_token_and_backspace_fixes_it() {
+        let mut app = App::default();
+        press(&mut app, "2+8/0=");
+        let line = app.highlighted_expression_line();
+        let highlighted: Vec<&str> = line
+            .spans
+            .iter()
+            .filter(|span| span.style.fg == Some(Color::Red))
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(highlighted, ["÷"]);
+
+        app.handle_key_events(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE));
+        assert!(app.error.is_none());
+        assert_eq!(app.expression_line(), "2 + 8 ÷");
+        press(&mut app, "4=");
+        assert_eq!(app.display_value(), "4");
+
+        press(&mut app, "a(1+2)*w0-4)+1=");
+        assert_eq!(
+            app.display_value(),
+            "Error square root of a negative number"
+        );
+        app.handle_key_events(KeyEvent::new(KeyCode::F(2), KeyModifiers::NONE));
+        let editor = app.editor.as_ref().unwrap();
+        assert_eq!(editor.text(), "(1 + 2) × sqrt(0 - 4) + 1");
+        assert_eq!(editor.cursor(), 10, "cursor on sqrt");
+    }
+
+    #[test]
+    fn unbalanced_parentheses_set_error() {
+        let mut app = App::default();
+        press(&mut app, "(1+2=");
+        assert_eq!(app.display_value(), "Error unbalanced parentheses");
+        assert_eq!(
+            app.error.and_then(|error| error.token),
+            Some(0),
+            "points at the open parenthesis"
+        );
+    }
+
+    #[test]
+    fn all_clear_resets_state() {
+        let mut app = App::default();
+        press(&mut app, "9-4=");
+        assert!(app.engine.just_evaluated());
+
+        app.all_clear();
+        assert!(app.engine.input().is_empty());
+        assert!(app.engine.tokens().is_empty());
+        assert!(app.error.is_none());
+        assert!(!app.engine.just_evaluated());
+    }
+
+    #[test]
+    fn history_records_and_recalls_results() {
+        let mut app = App::default();
+        press(&mut app, "2+3=");
+        press(&mut app, "=");
+        press(&mut app, "4x5=");
+        // a lone number is no calculation to record
+        press(&mut app, "a5=0.=");
+        assert_eq!(app.display_value(), "0");
+        let expressions: Vec<&str> = app
+            .history
+            .entries()
+            .iter()
+            .map(|entry| entry.expression.as_str())
+            .collect();
+        assert_eq!(expressions, ["2 + 3", "Ans + 3", "4 × 5"]);
+
+        let up = KeyEvent::new(KeyCode::Up, KeyModifiers::NONE);
+        for _ in 0..4 {
+            app.handle_key_events(up);
+        }
+        assert_eq!(app.history_selected, Some(0));
+
+        press(&mut app, "\n");
+        assert_eq!(app.history_selected, None);
+        assert_eq!(app.engine.input(), "5");
+
+        press(&mut app, "+1=");
+        assert_eq!(app.display_value(), "6");
+    }
+
+    #[test]
+    fn results_continue_as_ans() {
+        let mut app = App::default();
+        press(&mut app, "2+3=*2+v");
+        press(&mut app, "Ans\n");
+        assert_eq!(app.expression_line(), "Ans × 2 + Ans");
+        press(&mut app, "=");
+        assert_eq!(app.display_value(), "15");
+        assert_eq!(app.history.entries()[1].expression, "Ans × 2 + Ans");
+
+        press(&mut app, "v");
+        press(&mut app, "Ans2\n=");
+        assert_eq!(app.display_value(), "Error no such earlier result");
+    }
+
+    #[test]
+    fn history_selection_leaves_past_newest() {
+        let mut app = App::default();
+        press(&mut app, "1+1=");
+        app.handle_key_events(KeyEvent::new(KeyCode::Up, KeyModifiers::NONE));
+        assert_eq!(app.history_selected, Some(0));
+
+        app.handle_key_events(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        assert_eq!(app.history_selected, None);
+    }
+
+    #[test]
+    fn render_shows_expression_result_and_instructions() {
+        let app = App::default();
+        let area = Rect::new(0, 0, 60, 9);
+        let mut buf = Buffer::empty(area);
+
+        (&app).render(area, &mut buf);
+
+        assert!(row_string(&buf, 1, area.width).contains("Enter digits"));
+        assert!(row_string(&buf, 4, area.width).contains("0"));
+        assert!(row_string(&buf, 7, area.width).contains("Digits 0-9"));
+    }
+
+    #[test]
+    fn instructions_wrap_and_follow_the_mode() {
+        let screen = |app: &App| {
+            let area = Rect::new(0, 0, 108, 20);
+            let mut buf = Buffer::empty(area);
+            app.render(area, &mut buf);
+            (0..area.height)
+                .map(|row| row_string(&buf, row, area.width))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut app = App::default();
+        let text = screen(&app);
+        assert!(text.contains("%: percent") && text.contains("Q: quit"));
+        assert!(!text.contains("Tab: swap") && !text.contains("B: radix"));
+
+        press(&mut app, "p");
+        let text = screen(&app);
+        assert!(text.contains("Tab: swap") && text.contains("Q: quit"));
+        assert!(!text.contains("%: percent"));
+
+        press(&mut app, "pddd");
+        assert!(screen(&app).contains("B: radix"));
+
+        press(&mut app, "`");
+        let text = screen(&app);
+        assert!(text.contains("Ctrl+W: delete word") && !text.contains("Q: quit"));
+    }
+
+    #[test]
+    fn memory_keys_accumulate_and_recall() {
+        let mut app = App::default();
+        press(&mut app, "10m");
+        press(&mut app, "4m");
+        press(&mut app, "3n");
+        assert_eq!(app.engine.memory(), Some(&Value::Float(11.0)));
+
+        press(&mut app, "2xr=");
+        assert_eq!(app.display_value(), "22");
+
+        press(&mut app, "c");
+        assert_eq!(app.engine.memory(), None);
+    }
+
+    #[test]
+    fn variables_are_stored_and_used_through_prompt() {
+        let mut app = App::default();
+        press(&mut app, "6s");
+        assert_eq!(app.expression_line(), "Store as: _");
+        // letters go into the name instead of triggering shortcuts
+        press(&mut app, "rate\n");
+        assert!(app.name_prompt.is_none());
+
+        press(&mut app, "2+vrate\n");
+        assert_eq!(app.expression_line(), "2 + rate");
+        press(&mut app, "=");
+        assert_eq!(app.display_value(), "8");
+
+        press(&mut app, "vnope\n");
+        assert_eq!(app.display_value(), "Error unknown variable");
+    }
+
+    #[test]
+    fn decimal_backend_is_switchable_and_shown() {
+        let mut app = App::default();
+        assert_eq!(app.result_title(), "Result · f64 · rad");
+        press(&mut app, ".1+.2=");
+        assert_eq!(app.display_value(), "0.30000000000000004");
+
+        press(&mut app, "d");
+        press(&mut app, ".1+.2=");
+        assert_eq!(app.display_value(), "0.3");
+        assert_eq!(
+            app.result_title(),
+            "Result · decimal 34 digits, half-even · rad"
+        );
+
+        press(&mut app, "<<o");
+        assert_eq!(
+            app.result_title(),
+            "Result · decimal 32 digits, half-up · rad"
+        );
+
+        let area = Rect::new(0, 0, 80, 9);
+        let mut buf = Buffer::empty(area);
+        (&app).render(area, &mut buf);
+        assert!(row_string(&buf, 3, area.width).contains("Result · decimal"));
+    }
+
+    #[test]
+    fn rational_backend_toggles_fraction_display() {
+        let mut app = App::default();
+        press(&mut app, "dd");
+        assert_eq!(app.result_title(), "Result · rational (fraction) · rad");
+
+        press(&mut app, "7/3=");
+        assert_eq!(app.display_value(), "7/3");
+        press(&mut app, "f");
+        assert_eq!(app.display_value(), "2 1/3");
+        assert_eq!(app.result_title(), "Result · rational (mixed) · rad");
+        press(&mut app, "f");
+        assert!(app.display_value().starts_with("2.333"));
+
+        press(&mut app, "f");
+        press(&mut app, "x3=");
+        assert_eq!(app.display_value(), "7");
+    }
+
+    #[test]
+    fn angle_unit_key_cycles_and_applies_to_trig() {
+        let mut app = App::default();
+        press(&mut app, "u");
+        assert_eq!(app.result_title(), "Result · f64 · deg");
+        press(&mut app, "90i=");
+        assert_eq!(app.display_value(), "1");
+
+        press(&mut app, "u200k=");
+        assert_eq!(app.result_title(), "Result · f64 · grad");
+        assert_eq!(app.display_value(), "-1");
+
+        press(&mut app, "u");
+        assert_eq!(app.engine().angle_unit(), AngleUnit::Radians);
+    }
+
+    #[test]
+    fn ieee_key_switches_between_errors_and_infinities() {
+        let mut app = App::default();
+        press(&mut app, "1/0=");
+        assert_eq!(app.display_value(), "Error Cannot divide by zero");
+
+        press(&mut app, "a");
+        app.handle_key_events(KeyEvent::new(KeyCode::F(3), KeyModifiers::NONE));
+        assert_eq!(app.result_title(), "Result · f64 IEEE · rad");
+        press(&mut app, "1/0=");
+        assert_eq!(app.display_value(), "∞");
+        press(&mut app, "-1/0=");
+        assert_eq!(app.display_value(), "NaN", "∞ - ∞ chains to NaN");
+    }
+
+    #[test]
+    fn format_keys_reformat_the_result() {
+        let mut app = App::default();
+        press(&mut app, "1234567/8=");
+        assert_eq!(app.display_value(), "154320.875");
+
+        press(&mut app, ",]]]");
+        assert_eq!(app.display_value(), "154,320.88");
+        assert_eq!(app.result_title(), "Result · f64 · rad · 2 dp · 1,000s");
+
+        app.handle_key_events(KeyEvent::new(KeyCode::F(4), KeyModifiers::NONE));
+        app.handle_key_events(KeyEvent::new(KeyCode::F(4), KeyModifiers::NONE));
+        assert_eq!(app.display_value(), "1.54e5");
+        assert_eq!(
+            app.result_title(),
+            "Result · f64 · rad · sci · 2 dp · 1,000s"
+        );
+
+        press(&mut app, "[[[");
+        assert_eq!(app.display_value(), "1.54320875e5");
+        assert_eq!(app.engine().number_format().decimals, None);
+        press(&mut app, "*8=");
+        assert_eq!(app.display_value(), "1.234567e6");
+    }
+
+    #[test]
+    fn percent_key_works_like_a_desk_calculator() {
+        let mut app = App::default();
+        press(&mut app, "200+10%");
+        assert_eq!(app.expression_line(), "200 + 10%");
+        press(&mut app, "=");
+        assert_eq!(app.display_value(), "220");
+
+        press(&mut app, "50:200%=");
+        assert_eq!(app.display_value(), "25");
+
+        press(&mut app, "7%3=");
+        assert_eq!(app.display_value(), "1");
+
+        press(&mut app, "7%-3");
+        assert_eq!(app.expression_line(), "7 % -3");
+        press(&mut app, "=");
+        assert_eq!(app.display_value(), "-2");
+
+        press(&mut app, "p7\n3%");
+        assert_eq!(app.display_value(), "1");
+    }
+
+    #[test]
+    fn units_show_next_to_the_result() {
+        let mut app = App::default();
+        press(&mut app, "5v");
+        press(&mut app, "km\n");
+        assert_eq!(app.expression_line(), "5 km");
+        press(&mut app, "v");
+        press(&mut app, "to\nv");
+        press(&mut app, "mi\n=");
+        assert_eq!(app.display_value(), "3.1068559611866697 mi");
+        assert_eq!(app.history.entries()[0].result, "3.1068559611866697 mi");
+
+        press(&mut app, "*2=");
+        assert_eq!(app.display_value(), "6.2137119223733395 mi");
+
+        press(&mut app, "+1=");
+        assert_eq!(app.display_value(), "Error incompatible units");
+        assert_eq!(app.error.and_then(|error| error.token), Some(1));
+    }
+
+    #[test]
+    fn currency_results_show_the_rates_date() {
+        let (rates, _) = Rates::parse("as_of = 2026-10-01\nbase = EUR\nUSD = 1.25\n");
+        let mut app = App::default();
+        app.engine.set_rates(rates);
+
+        press(&mut app, "100v");
+        press(&mut app, "USD\nv");
+        press(&mut app, "to\nv");
+        press(&mut app, "EUR\n=");
+        assert_eq!(app.display_value(), "80 EUR");
+        assert_eq!(
+            app.result_title(),
+            "Result · f64 · rad · rates as of 2026-10-01"
+        );
+
+        press(&mut app, "/3=");
+        assert_eq!(
+            app.display_value(),
+            "26.66666666666666666666666666666667 EUR"
+        );
+        press(&mut app, "A2=");
+        assert_eq!(app.result_title(), "Result · f64 · rad");
+    }
+
+    #[test]
+    fn integer_mode_shows_every_radix_and_wrapping() {
+        let mut app = App::default();
+        press(&mut app, "dddbzj");
+        assert_eq!(app.result_title(), "Result · integer u8 · rad");
+
+        press(&mut app, "FAa");
+        assert_eq!(app.display_value(), "0");
+        press(&mut app, "FF+1=");
+        assert_eq!(app.result_title(), "Result · integer u8 · rad · wrapped");
+
+        press(&mut app, "C<2=");
+        let lines: Vec<String> = app.result_lines().iter().map(|l| l.to_string()).collect();
+        assert_eq!(lines, ["HEX 30", "DEC 48", "OCT 60", "BIN 110000"]);
+
+        let area = Rect::new(0, 0, 80, 12);
+        let mut buf = Buffer::empty(area);
+        (&app).render(area, &mut buf);
+        assert!(row_string(&buf, 7, area.width).contains("BIN 110000"));
+    }
+
+    #[test]
+    fn typed_expression_is_edited_in_the_middle_and_evaluated() {
+        let mut app = App::default();
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        press(&mut app, "12+3*");
+        press(&mut app, "`");
+        assert_eq!(app.editor.as_ref().unwrap().text(), "12 + 3 ×");
+
+        press(&mut app, " 4");
+        for _ in 0..4 {
+            app.handle_key_events(key(KeyCode::Left));
+        }
+        app.handle_key_events(key(KeyCode::Backspace));
+        press(&mut app, "5");
+        app.handle_key_events(key(KeyCode::Home));
+        press(&mut app, "(");
+        app.handle_key_events(KeyEvent::new(KeyCode::Right, KeyModifiers::CONTROL));
+        press(&mut app, ")");
+        assert_eq!(app.editor.as_ref().unwrap().text(), "(12) + 5 × 4");
+
+        press(&mut app, "\n");
+        assert!(app.editor.is_none());
+        assert_eq!(app.display_value(), "32");
+        assert_eq!(app.history().entries()[0].expression, "(12) + 5 × 4");
+    }
+
+    #[test]
+    fn typed_expression_assigns_a_variable() {
+        let mut app = App::default();
+        press(&mut app, "`");
+        press(&mut app, "x = 5\n");
+        assert!(app.editor.is_none());
+        assert_eq!(app.display_value(), "5");
+        assert_eq!(app.engine().variables()["x"].value, Value::Float(5.0));
+
+        press(&mut app, "a`");
+        press(&mut app, "x * 2\n");
+        assert_eq!(app.display_value(), "10");
+
+        press(&mut app, "a`");
+        press(&mut app, "km = 3\n");
+        assert_eq!(
+            app.editor_error.as_deref(),
+            Some("Error invalid variable name")
+        );
+    }
+
+    #[test]
+    fn preview_shows_the_running_total_under_the_expression() {
+        let mut app = App::default();
+        press(&mut app, "2+3*");
+        assert_eq!(app.preview(), Some("5".into()));
+        press(&mut app, "4");
+
+        let area = Rect::new(0, 0, 60, 9);
+        let mut buf = Buffer::empty(area);
+        (&app).render(area, &mut buf);
+        assert!(row_string(&buf, 2, area.width).contains("= 14"));
+
+        press(&mut app, "/0");
+        assert_eq!(app.preview(), None);
+        press(&mut app, "\n");
+        assert_eq!(app.display_value(), "Error Cannot divide by zero");
+
+        press(&mut app, "a`7-2");
+        assert_eq!(app.preview(), Some("5".into()));
+    }
+
+    #[test]
+    fn typed_expression_errors_keep_the_text() {
+        let mut app = App::default();
+        app.handle_key_events(KeyEvent::new(KeyCode::F(2), KeyModifiers::NONE));
+        press(&mut app, "8 / (2 - 2)\n");
+        assert_eq!(
+            app.editor_error.as_deref(),
+            Some("Error Cannot divide by zero")
+        );
+        assert_eq!(app.editor.as_ref().unwrap().text(), "8 / (2 - 2)");
+
+        let ctrl_w = KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL);
+        app.handle_key_events(ctrl_w);
+        app.handle_key_events(ctrl_w);
+        assert_eq!(app.editor.as_ref().unwrap().text(), "8 / (2 ");
+        assert_eq!(app.editor_error, None);
+        press(&mut app, "+ 2)\n");
+        assert_eq!(app.display_value(), "2");
+
+        press(&mut app, "p5`");
+        press(&mut app, " x 3\n");
+        assert_eq!(app.engine().stack(), [Value::Float(15.0)]);
+    }
+
+    #[test]
+    fn rpn_mode_keys_work_the_stack() {
+        let mut app = App::default();
+        press(&mut app, "p3\n4+2*");
+        assert_eq!(app.display_value(), "14");
+
+        press(&mut app, "9wy");
+        app.handle_key_events(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        press(&mut app, "-~");
+        let stack: Vec<String> = app.engine().stack().iter().map(Value::to_string).collect();
+        assert_eq!(stack, ["14", "0"]);
+
+        app.handle_key_events(KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE));
+        press(&mut app, "1\n2\n3h");
+        let lines: Vec<String> = app.stack_lines().iter().map(|l| l.to_string()).collect();
+        assert_eq!(lines, ["4: 3", "3: 14", "2: 1", "1: 2"]);
+
+        press(&mut app, "++++");
+        assert_eq!(app.display_value(), "Error stack needs two values");
+        press(&mut app, "a");
+        assert!(app.engine().stack().is_empty());
+    }
+
+    #[test]
+    fn rpn_mode_renders_the_stack_panel() {
+        let mut app = App::default();
+        press(&mut app, "p2\n5");
+
+        let area = Rect::new(0, 0, 60, 10);
+        let mut buf = Buffer::empty(area);
+        (&app).render(area, &mut buf);
+
+        assert!(row_string(&buf, 0, area.width).contains("Stack (RPN)"));
+        assert!(row_string(&buf, 1, area.width).contains("1: 2"));
+        assert!(row_string(&buf, 2, area.width).contains("5_"));
+    }
+
+    #[test]
+    fn render_shows_history_panel() {
+        let mut app = App::default();
+        press(&mut app, "2+3=");
+        let area = Rect::new(0, 0, 80, 9);
+        let mut buf = Buffer::empty(area);
+
+        (&app).render(area, &mut buf);
+
+        assert!(row_string(&buf, 0, area.width).contains("History"));
+        assert!(row_string(&buf, 1, area.width).contains("2 + 3 = 5"));
+    }
+
+    #[test]
+    fn render_shows_active_registers() {
+        let mut app = App::default();
+        press(&mut app, "5m7sw\n");
+        let area = Rect::new(0, 0, 80, 12);
+        let mut buf = Buffer::empty(area);
+
+        (&app).render(area, &mut buf);
+
+        assert!(row_string(&buf, 8, area.width).contains("Registers"));
+        assert!(row_string(&buf, 9, area.width).contains("M = 5"));
+        assert!(row_string(&buf, 10, area.width).contains("w = 7"));
+    }
+
+    fn row_string(buf: &Buffer, row: u16, width: u16) -> String {
+        let mut line = String::new();
+        for x in 0..width {
+            line.push_str(buf[(x, row)].symbol());
+        }
+        line
+    }
+}
@@ -0,0 +1,215 @@
+//! Exchange rates for currency units such as `100 USD to EUR`.
+//!
+//! Rates come from a file the user keeps up to date, never from a live
+//! service. The file holds simple `KEY = value` lines, a small subset of
+//! TOML: `base` names the currency the rates are quoted against, `as_of`
+//! says when they were taken, and every other key is a currency code with
+//! how much of it one unit of the base buys:
+//!
+//! ```toml
+//! as_of = 2026-10-01
+//! base = "EUR"
+//! USD = 1.0842
+//! GBP = 0.8563
+//! JPY = 161.2
+//! ```
+//!
+//! Codes are three capital letters, written without quotes, and rates are
+//! plain numbers. Blank lines and lines starting with `#` are ignored.
+//! Anything else, such as `[tables]`, quoted keys or a comment after a
+//! value, is skipped with a warning when the file is loaded.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use num_rational::BigRational;
+use num_traits::{One, Signed};
+
+use crate::{
+    settings::config_dir,
+    unit::Unit,
+    value::{Backend, Value},
+};
+
+/// Currencies and how much of each one unit of the base currency buys.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rates {
+    as_of: Option<String>,
+    base: Option<String>,
+    rates: BTreeMap<String, BigRational>,
+}
+
+impl Rates {
+    /// Parses the file format described in the module documentation,
+    /// returning the rates and the numbers of the lines that were skipped.
+    pub fn parse(text: &str) -> (Rates, Vec<usize>) {
+        let mut rates = Rates::default();
+        let mut skipped = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') && !rates.apply_line(line) {
+                skipped.push(index + 1);
+            }
+        }
+        if let Some(base) = &rates.base {
+            rates.rates.insert(base.clone(), BigRational::one());
+        }
+        (rates, skipped)
+    }
+
+    /// Applies one `key = value` line, returning whether it was understood.
+    fn apply_line(&mut self, line: &str) -> bool {
+        let Some((key, value)) = line.split_once('=') else {
+            return false;
+        };
+        let value = value.trim();
+        // a string may be quoted, as in TOML
+        let quoted = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'));
+        let text = quoted.unwrap_or(value);
+        match key.trim() {
+            // a `#` outside quotes would be a comment, which is not read
+            "as_of" if !text.is_empty() && (quoted.is_some() || !text.contains('#')) => {
+                self.as_of = Some(text.to_string())
+            }
+            "base" if is_currency_code(text) => self.base = Some(text.to_string()),
+            code if is_currency_code(code) => {
+                let Ok(Value::Rational(rate)) = Value::parse(value, Backend::Rational) else {
+                    return false;
+                };
+                if !rate.is_positive() {
+                    return false;
+                }
+                self.rates.insert(code.to_string(), rate);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// When the rates were taken, as written in the file.
+    pub fn as_of(&self) -> Option<&str> {
+        self.as_of.as_deref()
+    }
+
+    pub fn base(&self) -> Option<&str> {
+        self.base.as_deref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+
+    /// The currency codes, in alphabetical order.
+    pub fn currencies(&self) -> impl Iterator<Item = &str> {
+        self.rates.keys().map(String::as_str)
+    }
+
+    /// The unit of currency `code`, sized in units of the base currency.
+    pub(crate) fn unit(&self, code: &str) -> Option<Unit> {
+        let rate = self.rates.get(code)?;
+        Some(Unit::currency(code, &rate.recip()))
+    }
+}
+
+fn is_currency_code(text: &str) -> bool {
+    text.len() == 3 && text.bytes().all(|byte| byte.is_ascii_uppercase())
+}
+
+/// Reads [`Rates`] from the file format described in the module
+/// documentation.
+#[derive(Debug, Clone)]
+pub struct RatesStore {
+    path: PathBuf,
+}
+
+impl RatesStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `$XDG_CONFIG_HOME/calculator_cli/rates.toml`, next to the settings
+    /// file.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("rates.toml"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the rates. A missing file gives no currencies.
+    pub fn load(&self) -> io::Result<(Rates, Vec<String>)> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Ok((Rates::default(), Vec::new()));
+            }
+            Err(err) => return Err(err),
+        };
+
+        let (rates, skipped) = Rates::parse(&contents);
+        let warnings = skipped
+            .into_iter()
+            .map(|line| {
+                format!(
+                    "{}: skipping unknown rate on line {line}",
+                    self.path.display()
+                )
+            })
+            .collect();
+        Ok((rates, warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn parse_reads_rates_and_skips_bad_lines() {
+        let (rates, skipped) = Rates::parse(
+            "# rates from the bank\nas_of = \"2026-10-01\"\nbase = \"EUR\"\nUSD = 1.25\n\
+             GBP = 0\nyen = 160\nJPY = lots\n\nCHF = 0.95\n[more]\n\"AUD\" = 1.6\n\
+             NZD = 1.8 # buy\nSEK = \"11.5\"\nas_of = 2026-10-02 # late\n",
+        );
+
+        assert_eq!(rates.as_of(), Some("2026-10-01"));
+        assert_eq!(rates.base(), Some("EUR"));
+        assert_eq!(
+            rates.currencies().collect::<Vec<_>>(),
+            ["CHF", "EUR", "USD"]
+        );
+        assert_eq!(skipped, [5, 6, 7, 10, 11, 12, 13, 14]);
+        assert_eq!(
+            rates
+                .unit("USD")
+                .unwrap()
+                .conversion_to(&rates.unit("EUR").unwrap(), true),
+            Some((Value::parse("4/5", Backend::Rational).unwrap(), None))
+        );
+        assert_eq!(rates.unit("GBP"), None);
+    }
+
+    #[test]
+    fn load_warns_about_skipped_lines() {
+        let dir = TempDir::new("rates");
+        let store = RatesStore::new(dir.path().join("rates.toml"));
+
+        let (rates, warnings) = store.load().unwrap();
+        assert!(rates.is_empty());
+        assert!(warnings.is_empty());
+
+        fs::write(store.path(), "base = USD\nEUR = 0.9\nEUR: 0.8\n").unwrap();
+        let (rates, warnings) = store.load().unwrap();
+        assert_eq!(rates.currencies().collect::<Vec<_>>(), ["EUR", "USD"]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("line 3"));
+    }
+}
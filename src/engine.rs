@@ -0,0 +1,2714 @@
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, VecDeque},
+    ops::Range,
+};
+
+use crate::{
+    currency::Rates,
+    error::{Error, ErrorKind},
+    format::NumberFormat,
+    integer::{IntegerSettings, Radix},
+    settings::Settings,
+    token::{
+        Function, Operator, Token, answer_name, ends_with_operand, is_identifier, parse_answer,
+        tokenize,
+    },
+    unit::{MAX_UNIT_POWER, Quantity, Unit},
+    value::{AngleUnit, Backend, DecimalSettings, FloatMode, FractionStyle, Value},
+};
+
+/// Calculator state and evaluation, independent of any user interface.
+///
+/// `Engine` owns the number being typed (`input`), the committed `tokens`
+/// and whether `input` currently holds a result, plus the memory register
+/// and named variables and earlier results that outlive a single
+/// calculation. Front ends feed it
+/// key-sized edits and read back [`Engine::display`] and
+/// [`Engine::expression_line`].
+///
+/// Numbers stay text until evaluation, where the active [`Backend`] parses
+/// and combines them, so switching backends never loses typed digits. A
+/// result is kept in `input` in its exact [`Value`] text (`7/3` for a
+/// rational) and only formatted for display by [`Engine::display`]. In the
+/// integer backend, typed digits are in the active [`Radix`] and committed
+/// as prefixed literals such as `0xFF`.
+/// Operations that can fail return an [`Error`] and leave the expression in
+/// place, so the caller can show where it failed and let the user fix it.
+#[derive(Debug, Default, Clone)]
+pub struct Engine {
+    input: String,
+    tokens: Vec<Token>,
+    just_evaluated: bool,
+    /// The unit of a result in `input`, such as `km` for `5 km`.
+    unit: Unit,
+    /// The last operator of the last calculation and its right-hand
+    /// operand, such as `+ 3` after `5 + 3`, which Enter repeats on the
+    /// result.
+    repeat: Option<Vec<Token>>,
+    backend: Backend,
+    decimal: DecimalSettings,
+    fraction_style: FractionStyle,
+    number_format: NumberFormat,
+    angle_unit: AngleUnit,
+    float_mode: FloatMode,
+    integer: IntegerSettings,
+    /// Whether the last evaluation wrapped an integer around its word size.
+    wrapped: Cell<bool>,
+    /// The M+/M− register; `None` until something is stored or after MC.
+    memory: Option<Value>,
+    variables: BTreeMap<String, Quantity>,
+    /// Earlier results, oldest first, for `Ans` and `Ans1`… to refer to.
+    /// Not touched by [`Engine::clear`].
+    answers: VecDeque<Quantity>,
+    /// Exchange rates for currency units, loaded from the user's file.
+    rates: Rates,
+    /// The RPN operand stack, bottom first. Not touched by [`Engine::clear`]
+    /// so that an error does not lose it.
+    stack: Vec<Value>,
+}
+
+/// How many earlier results `AnsN` can reach back to.
+const ANSWER_LIMIT: usize = 100;
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds an engine whose tokens are the parsed `expression`.
+    pub fn from_expression(expression: &str) -> Result<Self, Error> {
+        Ok(Self {
+            tokens: tokenize(expression, &Rates::default())?,
+            ..Self::default()
+        })
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Whether `input` holds the result of the last evaluation.
+    pub fn just_evaluated(&self) -> bool {
+        self.just_evaluated
+    }
+
+    /// Whether the calculation so far is a single operand, such as `5` or
+    /// `-2 km`, with no operator, function or percentage to apply.
+    pub fn is_lone_operand(&self) -> bool {
+        !self.tokens.iter().any(|token| {
+            matches!(
+                token,
+                Token::Operator(_) | Token::Function(_) | Token::Percent
+            )
+        })
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.reinterpret_input(|engine| engine.backend = backend);
+    }
+
+    pub fn decimal_settings(&self) -> DecimalSettings {
+        self.decimal
+    }
+
+    pub fn set_decimal_settings(&mut self, settings: DecimalSettings) {
+        self.decimal = settings;
+    }
+
+    pub fn fraction_style(&self) -> FractionStyle {
+        self.fraction_style
+    }
+
+    pub fn set_fraction_style(&mut self, style: FractionStyle) {
+        self.fraction_style = style;
+    }
+
+    pub fn number_format(&self) -> NumberFormat {
+        self.number_format
+    }
+
+    /// Changes how results are shown. Results are kept exactly, so this
+    /// reformats the current one without losing digits.
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.number_format = format;
+    }
+
+    pub fn angle_unit(&self) -> AngleUnit {
+        self.angle_unit
+    }
+
+    pub fn set_angle_unit(&mut self, unit: AngleUnit) {
+        self.angle_unit = unit;
+    }
+
+    pub fn float_mode(&self) -> FloatMode {
+        self.float_mode
+    }
+
+    pub fn set_float_mode(&mut self, mode: FloatMode) {
+        self.float_mode = mode;
+    }
+
+    pub fn rates(&self) -> &Rates {
+        &self.rates
+    }
+
+    /// Replaces the exchange rates. Currencies missing from `rates` are no
+    /// longer units, so variables holding them fail when used. A variable
+    /// named like a new currency still takes precedence over it.
+    pub fn set_rates(&mut self, rates: Rates) {
+        self.rates = rates;
+    }
+
+    /// The backend, decimal, display and angle settings in one value, as
+    /// saved between sessions.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            backend: self.backend,
+            decimal: self.decimal,
+            fraction_style: self.fraction_style,
+            number_format: self.number_format,
+            angle_unit: self.angle_unit,
+            float_mode: self.float_mode,
+            integer: self.integer,
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: Settings) {
+        self.backend = settings.backend;
+        self.decimal = settings.decimal;
+        self.fraction_style = settings.fraction_style;
+        self.number_format = settings.number_format;
+        self.angle_unit = settings.angle_unit;
+        self.float_mode = settings.float_mode;
+        self.integer = settings.integer;
+    }
+
+    pub fn integer_settings(&self) -> IntegerSettings {
+        self.integer
+    }
+
+    /// Changes word size, signedness or radix. Digits being typed are
+    /// converted to the new radix.
+    pub fn set_integer_settings(&mut self, settings: IntegerSettings) {
+        self.reinterpret_input(|engine| engine.integer = settings);
+    }
+
+    /// Whether the last evaluation had to wrap an integer around the word
+    /// size, as in `255 + 1` giving `0` for `u8`.
+    pub fn wrapped(&self) -> bool {
+        self.wrapped.get()
+    }
+
+    /// Applies `change`, keeping the value of any digits being typed when
+    /// it affects how they are read.
+    fn reinterpret_input(&mut self, change: impl FnOnce(&mut Self)) {
+        let pending = if self.input.is_empty() || self.just_evaluated {
+            None
+        } else {
+            Value::parse(&self.input_literal(), self.backend).ok()
+        };
+        let before = (self.backend, self.integer.radix);
+        change(self);
+
+        let integer_input = |engine: &Self| {
+            engine.backend == Backend::Integer && engine.integer.radix != Radix::Dec
+        };
+        if let Some(value) = pending
+            && before != (self.backend, self.integer.radix)
+            && (before.0 == Backend::Integer || integer_input(self))
+        {
+            self.input = match value.into_backend(self.backend) {
+                Value::Integer(value) => self.integer.digits(value),
+                value => value.to_string(),
+            };
+        }
+    }
+
+    pub fn memory(&self) -> Option<&Value> {
+        self.memory.as_ref()
+    }
+
+    pub fn variables(&self) -> &BTreeMap<String, Quantity> {
+        &self.variables
+    }
+
+    /// Clears the current calculation. Memory and variables are kept.
+    pub fn clear(&mut self) {
+        self.input.clear();
+        self.tokens.clear();
+        self.just_evaluated = false;
+        self.repeat = None;
+        self.wrapped.set(false);
+    }
+
+    /// Appends a digit to the number being typed. In the integer backend
+    /// the digit must belong to the active radix, so `A`–`F` are digits in
+    /// hex; anything else is ignored.
+    pub fn push_digit(&mut self, digit: char) {
+        if self.backend == Backend::Integer && !digit.is_digit(self.integer.radix.base()) {
+            return;
+        }
+        let digit = digit.to_ascii_uppercase();
+        if self.just_evaluated {
+            self.input.clear();
+            self.just_evaluated = false;
+        }
+
+        if self.input == "0" || self.input == "-0" {
+            self.input.pop();
+        }
+
+        self.input.push(digit);
+    }
+
+    pub fn push_decimal_point(&mut self) {
+        if self.backend == Backend::Integer {
+            return;
+        }
+        if self.just_evaluated {
+            self.input.clear();
+            self.just_evaluated = false;
+        }
+
+        if self.input.is_empty() {
+            self.input.push('0');
+        }
+        if !self.input.contains('.') {
+            self.input.push('.');
+        }
+    }
+
+    /// Deletes the last typed digit. With no number being typed, takes back
+    /// the last token instead: a number is reopened for editing without its
+    /// last digit and anything else is removed, so a failed expression can
+    /// be fixed from the end.
+    pub fn backspace(&mut self) {
+        if self.just_evaluated {
+            return;
+        }
+        if self.input.is_empty() {
+            match self.tokens.pop() {
+                Some(Token::Number(text)) => {
+                    self.input = match Value::parse(&text, self.backend) {
+                        Ok(Value::Integer(value)) => self.integer.digits(value),
+                        _ => text,
+                    };
+                }
+                _ => return,
+            }
+        }
+        self.input.pop();
+        if self.input == "-" {
+            self.input.clear();
+        }
+    }
+
+    /// Flips the sign of the number being typed (±). With nothing typed yet,
+    /// toggles a unary minus in front of the next operand instead.
+    pub fn toggle_sign(&mut self) {
+        if self.input.is_empty() {
+            self.toggle_negate();
+            return;
+        }
+
+        match self.input.strip_prefix('-') {
+            Some(positive) => self.input = positive.to_string(),
+            None => self.input.insert(0, '-'),
+        }
+    }
+
+    fn toggle_negate(&mut self) {
+        if let Some(Token::Negate) = self.tokens.last() {
+            self.tokens.pop();
+        } else if !ends_with_operand(&self.tokens) {
+            self.tokens.push(Token::Negate);
+        }
+    }
+
+    /// Replaces the number being typed with `value`, e.g. a result recalled
+    /// from history. A quantity such as `5 km` comes back as a result, since
+    /// digits cannot be added to it.
+    pub fn recall(&mut self, value: &str) {
+        if let Some((number, unit)) = split_quantity(value, &self.rates) {
+            self.input = number.to_string();
+            self.unit = unit;
+            self.just_evaluated = true;
+            return;
+        }
+        self.input = match Value::parse(value, self.backend) {
+            Ok(Value::Integer(value)) => self.integer.digits(value),
+            _ => value.to_string(),
+        };
+        self.just_evaluated = false;
+    }
+
+    /// Adds the displayed value to memory (M+).
+    pub fn memory_add(&mut self) -> Result<(), Error> {
+        self.accumulate_memory(Operator::Add)
+    }
+
+    /// Subtracts the displayed value from memory (M−).
+    pub fn memory_subtract(&mut self) -> Result<(), Error> {
+        self.accumulate_memory(Operator::Subtract)
+    }
+
+    fn accumulate_memory(&mut self, operator: Operator) -> Result<(), Error> {
+        let value = self.display_number()?;
+        let memory = match &self.memory {
+            Some(memory) => memory.clone().into_backend(self.backend),
+            None => Value::parse("0", self.backend)?,
+        };
+        self.memory = Some(self.apply_operator(memory, value, operator)?);
+        self.finish_value_entry();
+        Ok(())
+    }
+
+    /// Puts the memory value into `input` (MR).
+    pub fn memory_recall(&mut self) {
+        if let Some(value) = &self.memory {
+            self.recall(&value.to_string());
+        }
+    }
+
+    /// Empties the memory register (MC).
+    pub fn memory_clear(&mut self) {
+        self.memory = None;
+    }
+
+    /// Stores the displayed value under `name`.
+    pub fn store_variable(&mut self, name: &str) -> Result<(), Error> {
+        if !self.is_variable_name(name) {
+            return Err(ErrorKind::InvalidVariableName.into());
+        }
+        let value = self.display_number()?;
+        let unit = self.result_unit().cloned().unwrap_or_default();
+        self.variables
+            .insert(name.to_string(), Quantity { value, unit });
+        self.finish_value_entry();
+        Ok(())
+    }
+
+    /// Whether `name` can name a variable: an identifier that is not also
+    /// a unit or currency, so that `5 s` is always five seconds.
+    fn is_variable_name(&self, name: &str) -> bool {
+        is_identifier(name) && Unit::named(name, &self.rates).is_none()
+    }
+
+    /// Appends a reference to variable `name` as the next operand, or the
+    /// unit `name` when there is no such variable. A unit directly follows
+    /// the number it belongs to, as in `5 km`. `Ans` and `Ans1`… refer to
+    /// earlier results.
+    pub fn push_variable(&mut self, name: &str) -> Result<(), Error> {
+        let answer = parse_answer(name);
+        if answer.is_some_and(|back| self.answer(back).is_none()) {
+            return Err(ErrorKind::NoAnswer.into());
+        }
+        let unit = answer.is_none()
+            && !self.variables.contains_key(name)
+            && Unit::named(name, &self.rates).is_some();
+        if answer.is_none() && !self.variables.contains_key(name) && !unit {
+            return Err(ErrorKind::UnknownVariable.into());
+        }
+        if self.just_evaluated && !unit {
+            self.input.clear();
+            self.just_evaluated = false;
+        }
+        self.commit_input()?;
+        self.operand_follows();
+
+        if ends_with_operand(&self.tokens) && !unit {
+            // `2 rate` reads as `2 × rate`
+            self.tokens.push(Token::Operator(Operator::Multiply));
+        }
+        self.tokens.push(match answer {
+            Some(back) => Token::Answer(back),
+            None => Token::Variable(name.to_string()),
+        });
+        Ok(())
+    }
+
+    /// The result `back` results before the last one.
+    fn answer(&self, back: usize) -> Option<&Quantity> {
+        self.answers.iter().rev().nth(back)
+    }
+
+    /// Whether the result on display is the last one, which then
+    /// continues a calculation as `Ans`.
+    fn showing_answer(&self) -> bool {
+        self.just_evaluated
+            && self.answer(0).is_some_and(|answer| {
+                answer.unit == self.unit && answer.value.to_string() == self.input
+            })
+    }
+
+    fn push_answer(&mut self, answer: Quantity) {
+        if self.answers.len() == ANSWER_LIMIT {
+            self.answers.pop_front();
+        }
+        self.answers.push_back(answer);
+    }
+
+    /// The number shown in a result display, as a value.
+    pub fn display_number(&self) -> Result<Value, Error> {
+        if let Some(top) = self.stack_top() {
+            return Ok(top.clone());
+        }
+        let value = if self.input.is_empty() {
+            Value::parse(&self.current_number(), self.backend)
+        } else {
+            let backend = self
+                .result_unit()
+                .map_or(self.backend, |unit| self.backend_for(unit));
+            Value::parse(&self.input_literal(), backend)
+        };
+        Ok(value?)
+    }
+
+    /// After a memory or variable store, the next digit starts a new number
+    /// just like after an evaluation. A stored number that was typed rather
+    /// than calculated is not a result to repeat the last operation on.
+    fn finish_value_entry(&mut self) {
+        if !self.input.is_empty() {
+            if !self.just_evaluated {
+                self.unit = Unit::default();
+                self.repeat = None;
+            }
+            self.just_evaluated = true;
+        }
+    }
+
+    pub fn push_operator(&mut self, operator: Operator) -> Result<(), Error> {
+        self.commit_input()?;
+
+        if operator == Operator::Subtract && !ends_with_operand(&self.tokens) {
+            // nothing to subtract from, so `-` negates the next operand
+            self.toggle_negate();
+            return Ok(());
+        }
+        if operator == Operator::Subtract && self.tokens.last() == Some(&Token::Percent) {
+            // `7 % -3`: the `%` was the remainder and the `-` a sign
+            self.operand_follows();
+            self.toggle_negate();
+            return Ok(());
+        }
+        if let Some(Token::Negate) = self.tokens.last() {
+            self.tokens.pop();
+        }
+
+        match self.tokens.last_mut() {
+            // no operand to attach the operator to
+            None | Some(Token::LeftParen | Token::Function(_)) => return Ok(()),
+            // `/` twice is floor division, as when typed
+            Some(Token::Operator(current @ Operator::Divide)) if operator == Operator::Divide => {
+                *current = Operator::FloorDivide
+            }
+            Some(Token::Operator(current)) => *current = operator,
+            _ => self.tokens.push(Token::Operator(operator)),
+        }
+        self.just_evaluated = false;
+        Ok(())
+    }
+
+    /// Applies `function` to the number being typed or the last result, as
+    /// in `sqrt(16)`. With no number pending it opens `sqrt(` for the
+    /// argument to follow.
+    pub fn push_function(&mut self, function: Function) -> Result<(), Error> {
+        self.operand_follows();
+        if !self.input.is_empty() {
+            self.tokens
+                .extend([Token::Function(function), Token::LeftParen]);
+            self.commit_input()?;
+            self.tokens.push(Token::RightParen);
+            return Ok(());
+        }
+
+        if ends_with_operand(&self.tokens) {
+            // `2 sqrt(` reads as `2 × sqrt(`
+            self.tokens.push(Token::Operator(Operator::Multiply));
+        }
+        self.tokens
+            .extend([Token::Function(function), Token::LeftParen]);
+        Ok(())
+    }
+
+    pub fn open_paren(&mut self) -> Result<(), Error> {
+        if self.just_evaluated {
+            self.input.clear();
+            self.just_evaluated = false;
+        }
+        self.commit_input()?;
+        self.operand_follows();
+
+        if ends_with_operand(&self.tokens) {
+            // `2 (3 + 4)` reads as `2 × (3 + 4)`
+            self.tokens.push(Token::Operator(Operator::Multiply));
+        }
+        self.tokens.push(Token::LeftParen);
+        Ok(())
+    }
+
+    pub fn close_paren(&mut self) -> Result<(), Error> {
+        self.commit_input()?;
+
+        match self.tokens.last() {
+            // nothing to close yet, e.g. `()` or `2 + )`
+            None
+            | Some(Token::Operator(_) | Token::Negate | Token::Function(_) | Token::LeftParen) => {}
+            _ => self.tokens.push(Token::RightParen),
+        }
+        Ok(())
+    }
+
+    /// Makes the number being typed, or the operand before, a percentage
+    /// (`%`). What it is a percentage of depends on the operator before it:
+    /// see [`Engine::evaluate_tokens`].
+    pub fn push_percent(&mut self) -> Result<(), Error> {
+        self.commit_input()?;
+        if ends_with_operand(&self.tokens) && self.tokens.last() != Some(&Token::Percent) {
+            self.tokens.push(Token::Percent);
+        }
+        Ok(())
+    }
+
+    /// A `%` with an operand entered after it is the remainder operator
+    /// after all, as when typed: `7 % 3`.
+    fn operand_follows(&mut self) {
+        if let Some(token @ Token::Percent) = self.tokens.last_mut() {
+            *token = Token::Operator(Operator::Modulo);
+        }
+    }
+
+    /// Evaluates the committed expression and leaves the formatted result in
+    /// `input`. Incomplete expressions are left untouched.
+    ///
+    /// Evaluating again with a result on display repeats the last operator
+    /// and its right-hand operand on it, as on a desk calculator: `5 + 3`
+    /// then Enter, Enter gives `8` then `11`, and `× 1.2` keeps scaling.
+    ///
+    /// Each new result becomes `Ans`, and the one before it `Ans1`.
+    pub fn evaluate(&mut self) -> Result<(), Error> {
+        let repeat = self.pending_repeat().cloned();
+        // Enter on a result on display alone is not a new calculation
+        let fresh = !self.just_evaluated || !self.tokens.is_empty() || repeat.is_some();
+        self.commit_input()?;
+        if let Some(repeat) = &repeat {
+            self.tokens.extend(repeat.iter().cloned());
+        }
+        self.wrapped.set(false);
+        if let Some(Token::Operator(_) | Token::Negate | Token::Function(_) | Token::LeftParen) =
+            self.tokens.last()
+        {
+            // trailing operator or open group means expression is incomplete
+            return Ok(());
+        }
+        if self.tokens.is_empty() {
+            return Ok(());
+        }
+
+        let result = self.evaluate_tokens()?;
+        if repeat.is_none() {
+            self.repeat = last_operation(&self.tokens).map(|tokens| self.resolve_answers(tokens));
+        }
+        self.input = result.value.to_string();
+        self.unit = result.unit.clone();
+        if fresh {
+            self.push_answer(result);
+        }
+        self.tokens.clear();
+        self.just_evaluated = true;
+        Ok(())
+    }
+
+    /// The operation [`Engine::evaluate`] would repeat on the result on
+    /// display, if any.
+    fn pending_repeat(&self) -> Option<&Vec<Token>> {
+        self.repeat
+            .as_ref()
+            .filter(|_| self.just_evaluated && self.tokens.is_empty())
+    }
+
+    /// `tokens` with each `Ans` replaced by the result it refers to now, so
+    /// that a repeated `+ Ans` keeps adding the same amount.
+    fn resolve_answers(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .flat_map(|token| match token {
+                Token::Answer(back) if let Some(answer) = self.answer(back) => {
+                    let number = match answer.value {
+                        Value::Integer(value) => self.integer.literal(value),
+                        ref value => value.to_string(),
+                    };
+                    answer.unit.quantity_tokens(number)
+                }
+                token => vec![token],
+            })
+            .collect()
+    }
+
+    /// The calculation [`Engine::evaluate`] would repeat on the result on
+    /// display, such as `Ans + 3`, or `None` when it would not repeat one.
+    pub fn repeat_line(&self) -> Option<String> {
+        let repeat = self.pending_repeat()?;
+        let mut engine = self.clone();
+        engine.commit_input().ok()?;
+        engine.tokens.extend(repeat.iter().cloned());
+        Some(engine.expression_line())
+    }
+
+    /// Evaluates the committed tokens.
+    ///
+    /// A percentage right of `+`, `-`, `×` or `÷` has the usual business
+    /// calculator meaning: `200 + 10%` adds 10% of 200 (`220`), `200 × 10%`
+    /// takes 10% of it (`20`) and `50 ÷ 200%` gives the whole that 50 is
+    /// 200% of (`25`). Anywhere else, as in `10%` alone or the `10% × 3` of
+    /// `200 + 10% × 3`, a percentage is a hundredth.
+    pub fn evaluate_tokens(&self) -> Result<Quantity, Error> {
+        let mut pos = 0;
+        let result = self.parse_binary(&mut pos, 0)?;
+        match self.tokens.get(pos) {
+            None => Ok(result),
+            Some(Token::RightParen) => Err(Error::at(ErrorKind::UnbalancedParentheses, pos)),
+            Some(_) => Err(Error::at(ErrorKind::InvalidExpression, pos)),
+        }
+    }
+
+    /// Parses operands joined by operators that bind at least as tightly as
+    /// `min_precedence`, starting at `pos`.
+    ///
+    /// Precedence and associativity come from [`Operator::precedence`] and
+    /// [`Operator::is_right_associative`]: the right-hand side of a
+    /// left-associative operator only takes tighter operators, while a
+    /// right-associative one also takes its own tier.
+    fn parse_binary(&self, pos: &mut usize, min_precedence: u8) -> Result<Quantity, Error> {
+        let operand = self.parse_operand(pos)?;
+        self.continue_binary(operand, pos, min_precedence)
+    }
+
+    /// [`Engine::parse_binary`] after its first operand, `operand`.
+    fn continue_binary(
+        &self,
+        operand: Quantity,
+        pos: &mut usize,
+        min_precedence: u8,
+    ) -> Result<Quantity, Error> {
+        let mut result = self.percent_suffix(pos, operand)?;
+        while let Some(Token::Operator(op)) = self.tokens.get(*pos) {
+            let precedence = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            let index = *pos;
+            *pos += 1;
+            let rhs_precedence = if op.is_right_associative() {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let operand = self.parse_operand(pos)?;
+            let applied = if self.is_percentage_of(*pos, *op, rhs_precedence) {
+                *pos += 1;
+                self.apply_percentage(result, operand, *op)
+            } else {
+                let rhs = self.continue_binary(operand, pos, rhs_precedence)?;
+                self.apply_quantities(result, rhs, *op)
+            };
+            result = applied.map_err(|kind| Error::at(kind, index))?;
+        }
+        Ok(result)
+    }
+
+    /// `operand` as a fraction if a `%` at `pos` follows it: `10%` is
+    /// `0.1`.
+    fn percent_suffix(&self, pos: &mut usize, operand: Quantity) -> Result<Quantity, Error> {
+        if self.tokens.get(*pos) != Some(&Token::Percent) {
+            return Ok(operand);
+        }
+        *pos += 1;
+        let hundred = Value::parse("100", self.backend)?;
+        self.apply_quantities(operand, hundred.into(), Operator::Divide)
+            .map_err(|kind| Error::at(kind, *pos - 1))
+    }
+
+    /// Whether a `%` at `pos` makes the operand before it a percentage of
+    /// the left-hand side of `operator`, as in `200 + 10%`. It does not
+    /// when a tighter operator follows, as in `200 + 10% × 3`.
+    fn is_percentage_of(&self, pos: usize, operator: Operator, rhs_precedence: u8) -> bool {
+        let binds_tighter = matches!(
+            self.tokens.get(pos + 1),
+            Some(Token::Operator(next)) if next.precedence() >= rhs_precedence
+        );
+        let takes_percentage = matches!(
+            operator,
+            Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide
+        );
+        self.tokens.get(pos) == Some(&Token::Percent) && takes_percentage && !binds_tighter
+    }
+
+    /// `lhs operator percentage%`, as described at
+    /// [`Engine::evaluate_tokens`]. The percentage is applied before
+    /// dividing by 100 so that whole numbers stay whole in the integer
+    /// backend.
+    fn apply_percentage(
+        &self,
+        lhs: Quantity,
+        percentage: Quantity,
+        operator: Operator,
+    ) -> Result<Quantity, ErrorKind> {
+        let hundred = Quantity::from(Value::parse("100", self.backend)?);
+        let share = |whole: Quantity| {
+            let scaled = self.apply_quantities(whole, percentage.clone(), Operator::Multiply)?;
+            self.apply_quantities(scaled, hundred.clone(), Operator::Divide)
+        };
+        match operator {
+            Operator::Add | Operator::Subtract => {
+                let part = share(lhs.clone())?;
+                self.apply_quantities(lhs, part, operator)
+            }
+            Operator::Multiply => share(lhs),
+            _ => {
+                let scaled = self.apply_quantities(lhs, hundred.clone(), Operator::Multiply)?;
+                self.apply_quantities(scaled, percentage, Operator::Divide)
+            }
+        }
+    }
+
+    fn parse_number(&self, text: &str) -> Result<Value, ErrorKind> {
+        match Value::parse(text, self.backend)? {
+            // a radix literal is a bit pattern, so `0xFF` is `-1` in a
+            // signed byte rather than an overflow
+            Value::Integer(bits)
+                if Radix::strip_prefix(text).is_some() && bits >> self.integer.bits == 0 =>
+            {
+                Ok(Value::Integer(self.integer.from_bits(bits as u128)))
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Parses a single number, variable, earlier result, unit or a parenthesized
+    /// sub-expression, with any unary minus or function in front of it and
+    /// any unit after it. A unary minus covers a following power, so
+    /// `-2 ^ 2` is `-4`.
+    fn parse_operand(&self, pos: &mut usize) -> Result<Quantity, Error> {
+        let index = *pos;
+        let at = |kind| Error::at(kind, index);
+        let quantity = match self.tokens.get(index) {
+            Some(Token::Number(text)) => {
+                *pos += 1;
+                let value = match self.unit_at(*pos) {
+                    // an amount of money is read as written, not as an f64
+                    Some(unit) if unit.is_money() => Value::parse(text, self.backend_for(&unit)),
+                    _ => self.parse_number(text),
+                }
+                .map_err(|_| at(ErrorKind::InvalidNumber))?;
+                self.with_unit_suffix(pos, value.into())
+            }
+            Some(Token::Variable(name)) => {
+                *pos += 1;
+                match self.variables.get(name) {
+                    Some(quantity) => {
+                        let backend = self.backend_for(&quantity.unit);
+                        self.with_unit_suffix(pos, quantity.clone().into_backend(backend))
+                    }
+                    // a bare unit, as in `km/h`, is one of it
+                    None => {
+                        let unit = self.named_unit(index)?;
+                        let one = Value::parse("1", self.backend)?;
+                        Ok(Quantity { value: one, unit })
+                    }
+                }
+            }
+            Some(Token::Answer(back)) => {
+                *pos += 1;
+                let answer = self.answer(*back).ok_or(at(ErrorKind::NoAnswer))?;
+                let backend = self.backend_for(&answer.unit);
+                self.with_unit_suffix(pos, answer.clone().into_backend(backend))
+            }
+            Some(Token::Negate) => {
+                *pos += 1;
+                let operand = self.parse_operand(pos)?;
+                // a `%` is left to the negated operand, so that `200 + -10%`
+                // takes 10% off like `200 - 10%`
+                if self.tokens.get(*pos) == Some(&Token::Percent) {
+                    Ok(operand.negate())
+                } else {
+                    self.continue_binary(operand, pos, Operator::Power.precedence())
+                        .map(Quantity::negate)
+                }
+            }
+            Some(Token::Function(Function::Not)) => {
+                *pos += 1;
+                match self.parse_operand(pos)? {
+                    Quantity {
+                        value: Value::Integer(value),
+                        ..
+                    } => Ok(Value::Integer(self.integer.not(value)).into()),
+                    _ => Err(at(ErrorKind::BitwiseNeedsInteger)),
+                }
+            }
+            Some(Token::Function(function)) => {
+                *pos += 1;
+                let operand = self.parse_operand(pos)?;
+                self.apply_function(operand, *function).map_err(at)
+            }
+            Some(Token::LeftParen) => {
+                *pos += 1;
+                let result = self.parse_binary(pos, 0)?;
+                match self.tokens.get(*pos) {
+                    Some(Token::RightParen) => {
+                        *pos += 1;
+                        self.with_unit_suffix(pos, result)
+                    }
+                    // point at the group left open
+                    _ => Err(at(ErrorKind::UnbalancedParentheses)),
+                }
+            }
+            Some(_) => Err(at(ErrorKind::InvalidExpression)),
+            None => Err(ErrorKind::IncompleteExpression.into()),
+        }?;
+        Ok(Quantity {
+            value: self.fit_integer(quantity.value),
+            unit: quantity.unit,
+        })
+    }
+
+    /// Wraps an integer into the configured word, noting when that changes
+    /// it. Other values pass through.
+    fn fit_integer(&self, value: Value) -> Value {
+        let Value::Integer(value) = value else {
+            return value;
+        };
+        let (value, wrapped) = self.integer.wrap(value);
+        if wrapped {
+            self.wrapped.set(true);
+        }
+        Value::Integer(value)
+    }
+
+    /// The pending input as number text for [`Value::parse`]: digits typed
+    /// in the integer backend get the prefix of the active radix.
+    fn input_literal(&self) -> String {
+        if self.backend != Backend::Integer || self.just_evaluated {
+            return self.input.clone();
+        }
+        match self.input.strip_prefix('-') {
+            Some(digits) => format!("-{}{digits}", self.integer.radix.prefix()),
+            None => format!("{}{}", self.integer.radix.prefix(), self.input),
+        }
+    }
+
+    fn commit_input(&mut self) -> Result<(), Error> {
+        if self.input.is_empty() {
+            return Ok(());
+        }
+
+        let literal = self.input_literal();
+        match Value::parse(&literal, self.backend) {
+            Ok(value) => {
+                let number = match value {
+                    Value::Integer(value) => self.integer.literal(value),
+                    _ => literal,
+                };
+                self.operand_follows();
+                if self.showing_answer() {
+                    // continuing from the last result, as in `Ans × 2`
+                    self.unit = Unit::default();
+                    self.tokens.push(Token::Answer(0));
+                } else if self.just_evaluated {
+                    let unit = std::mem::take(&mut self.unit);
+                    self.tokens.extend(unit.quantity_tokens(number));
+                } else {
+                    self.tokens.push(Token::Number(number));
+                }
+                self.input.clear();
+                self.just_evaluated = false;
+                Ok(())
+            }
+            Err(_) => Err(ErrorKind::InvalidNumber.into()),
+        }
+    }
+
+    fn apply_operator(
+        &self,
+        lhs: Value,
+        rhs: Value,
+        operator: Operator,
+    ) -> Result<Value, ErrorKind> {
+        // the percentage helpers are built from the arithmetic below, with
+        // the division last so that whole numbers stay whole
+        let backend = lhs.backend();
+        let hundred = || Value::parse("100", backend);
+        match operator {
+            Operator::PercentChange => {
+                let change = self.apply_operator(rhs, lhs.clone(), Operator::Subtract)?;
+                let scaled = self.apply_operator(change, hundred()?, Operator::Multiply)?;
+                return self.apply_operator(scaled, lhs, Operator::Divide);
+            }
+            Operator::Markup => {
+                let factor = self.apply_operator(hundred()?, rhs, Operator::Add)?;
+                let scaled = self.apply_operator(lhs, factor, Operator::Multiply)?;
+                return self.apply_operator(scaled, hundred()?, Operator::Divide);
+            }
+            Operator::Margin => {
+                let rest = self.apply_operator(hundred()?, rhs, Operator::Subtract)?;
+                let scaled = self.apply_operator(lhs, hundred()?, Operator::Multiply)?;
+                return self.apply_operator(scaled, rest, Operator::Divide);
+            }
+            _ => {}
+        }
+        if let (Value::Integer(lhs), Value::Integer(rhs)) = (&lhs, &rhs) {
+            let (value, wrapped) = self.integer.apply(*lhs, *rhs, operator)?;
+            if wrapped {
+                self.wrapped.set(true);
+            }
+            return Ok(Value::Integer(value));
+        }
+        lhs.apply(operator, rhs, &self.decimal, self.float_mode)
+    }
+
+    /// Formats `value` for display, following the fraction style and number
+    /// format, or the radix for integers.
+    pub fn format_value(&self, value: &Value) -> String {
+        match value {
+            Value::Integer(value) => self.integer.format(*value, self.integer.radix),
+            value => value.format(self.fraction_style, &self.decimal, &self.number_format),
+        }
+    }
+
+    /// Formats `quantity` for display, with its unit after the number.
+    pub fn format_quantity(&self, quantity: &Quantity) -> String {
+        let number = self.format_value(&quantity.value);
+        if quantity.unit.is_empty() {
+            number
+        } else {
+            format!("{number} {}", quantity.unit)
+        }
+    }
+
+    /// Formats the exact text of a stored value, such as a history result
+    /// or a quantity like `5 km`, for display. Text that does not parse is
+    /// returned unchanged.
+    pub fn format_text(&self, text: &str) -> String {
+        if let Some((number, unit)) = split_quantity(text, &self.rates)
+            && let Ok(value) = Value::parse(number, self.backend_for(&unit))
+        {
+            return self.format_quantity(&Quantity { value, unit });
+        }
+        Value::parse(text, self.backend)
+            .map(|value| self.format_value(&value))
+            .unwrap_or_else(|_| text.to_string())
+    }
+
+    /// The unit of the result on display, such as `km` after `5 km`; `None`
+    /// for a plain number or while one is typed.
+    pub fn result_unit(&self) -> Option<&Unit> {
+        (self.just_evaluated && !self.unit.is_empty()).then_some(&self.unit)
+    }
+
+    /// The result on display as exact text with its unit, such as
+    /// `3.5 km`, which [`Engine::recall`] and [`Engine::format_text`] read
+    /// back.
+    pub fn result_text(&self) -> String {
+        match self.result_unit() {
+            Some(unit) => format!("{} {unit}", self.input),
+            None => self.input.clone(),
+        }
+    }
+
+    /// The value to show in a result display: the number being typed, or the
+    /// last committed number, or the top of the RPN stack, or `0`. The unit
+    /// of a result is in [`Engine::result_unit`].
+    pub fn display(&self) -> String {
+        if self.just_evaluated {
+            return match Value::parse(&self.input, self.backend_for(&self.unit)) {
+                Ok(value) => self.format_value(&value),
+                Err(_) => self.input.clone(),
+            };
+        }
+        if let Some(top) = self.stack_top() {
+            return self.format_value(top);
+        }
+        self.current_number()
+    }
+
+    fn current_number(&self) -> String {
+        if !self.input.is_empty() {
+            return self.input.clone();
+        }
+        if let Some(value) = self.tokens.iter().rev().find_map(|token| match token {
+            Token::Number(number) => Some(number.clone()),
+            _ => None,
+        }) {
+            return value;
+        }
+        "0".into()
+    }
+
+    /// The committed tokens followed by the pending input, e.g.
+    /// `(2 + 3) × 4`. Empty when nothing has been entered.
+    ///
+    /// Binary operators are spaced and a unary minus hugs its operand, so
+    /// `2 - -3` and `3 × -(1 + 1)` read unambiguously. Functions hug an
+    /// opening parenthesis: `sqrt(2)`, but `sin 30`.
+    pub fn expression_line(&self) -> String {
+        self.line_with_input(&self.input)
+    }
+
+    /// The pending calculation as text that [`Engine::set_expression`]
+    /// reads back, for editing it as a whole. Unlike
+    /// [`Engine::expression_line`] a number being typed in another radix
+    /// keeps its prefix.
+    pub fn expression_text(&self) -> String {
+        self.line_with_input(&self.input_literal())
+    }
+
+    /// Where token `index` appears in [`Engine::expression_line`], as a
+    /// byte range, e.g. to highlight the token an [`Error`] is about.
+    pub fn expression_token_range(&self, index: usize) -> Option<Range<usize>> {
+        self.layout_line(&self.input).1.get(index).cloned()
+    }
+
+    fn line_with_input(&self, input: &str) -> String {
+        self.layout_line(input).0
+    }
+
+    /// The expression line with `input` as the number being typed, and the
+    /// byte range of each token in it.
+    fn layout_line(&self, input: &str) -> (String, Vec<Range<usize>>) {
+        let mut line = String::new();
+        let mut ranges = Vec::with_capacity(self.tokens.len());
+        // parentheses hug their contents and a percent sign its operand:
+        // `(2 + 3) × 4%`
+        let mut hug_next = true;
+        let mut push = |part: &str, hugs_previous: bool, hugs_next: bool| {
+            if !hug_next && !hugs_previous {
+                line.push(' ');
+            }
+            ranges.push(line.len()..line.len() + part.len());
+            line.push_str(part);
+            hug_next = hugs_next;
+        };
+
+        for (index, token) in self.tokens.iter().enumerate() {
+            match token {
+                Token::Function(function) => {
+                    let hugs_next = self.tokens.get(index + 1) == Some(&Token::LeftParen);
+                    push(function.name(), false, hugs_next)
+                }
+                Token::Number(number) | Token::Variable(number) => push(number, false, false),
+                Token::Answer(back) => push(&answer_name(*back), false, false),
+                Token::Negate => push("-", false, true),
+                // with a number typed after it, it is the remainder
+                Token::Percent => {
+                    let remainder = index + 1 == self.tokens.len() && !input.is_empty();
+                    push("%", !remainder, false)
+                }
+                Token::Operator(op) => push(op.symbol(), false, false),
+                Token::LeftParen => push("(", false, true),
+                Token::RightParen => push(")", true, false),
+            }
+        }
+        if !input.is_empty() {
+            push(input, false, false);
+        }
+        (line, ranges)
+    }
+}
+
+impl Engine {
+    /// Replaces the pending calculation with the tokens of `expression`, as
+    /// if it had been entered key by key.
+    pub fn set_expression(&mut self, expression: &str) -> Result<(), Error> {
+        self.clear();
+        self.tokens = tokenize(expression, &self.rates)?;
+        Ok(())
+    }
+
+    /// Splits a typed line of the form `name = expression` into the
+    /// variable name and the expression; a line without `=` has no name.
+    pub fn split_assignment<'a>(&self, line: &'a str) -> Result<(Option<&'a str>, &'a str), Error> {
+        let Some((name, expression)) = line.split_once('=') else {
+            return Ok((None, line));
+        };
+        let name = name.trim();
+        if !self.is_variable_name(name) {
+            return Err(ErrorKind::InvalidVariableName.into());
+        }
+        Ok((Some(name), expression))
+    }
+
+    /// Evaluates a complete typed line without any key handling, using the
+    /// same tokens, evaluator and formatting as the interactive calculator.
+    ///
+    /// A line of the form `name = expression` also stores the result in
+    /// variable `name` for later lines, and every result becomes their
+    /// `Ans`. The pending calculation is replaced.
+    pub fn evaluate_line(&mut self, line: &str) -> Result<String, Error> {
+        let (name, expression) = self.split_assignment(line)?;
+        self.set_expression(expression)?;
+        if self.tokens.is_empty() {
+            return Err(ErrorKind::EmptyExpression.into());
+        }
+        let result = self.evaluate_tokens();
+        self.tokens.clear();
+        let result = result?;
+
+        let formatted = self.format_quantity(&result);
+        if let Some(name) = name {
+            self.variables.insert(name.to_string(), result.clone());
+        }
+        self.push_answer(result);
+        Ok(formatted)
+    }
+
+    /// The result the calculation so far would give if it ended here, for
+    /// a running total: a trailing operator, function or `(` is ignored and
+    /// open groups are closed. `None` when there is nothing to calculate
+    /// yet or the partial expression fails, so errors wait for Enter.
+    pub fn preview(&self) -> Option<String> {
+        if self.just_evaluated {
+            return None;
+        }
+        let mut engine = self.clone();
+        engine.commit_input().ok()?;
+        engine.preview_tokens()
+    }
+
+    /// Like [`Engine::preview`], for an expression typed as text.
+    pub fn preview_expression(&self, expression: &str) -> Option<String> {
+        let mut engine = self.clone();
+        engine.input.clear();
+        engine.tokens = tokenize(expression, &self.rates).ok()?;
+        engine.preview_tokens()
+    }
+
+    fn preview_tokens(mut self) -> Option<String> {
+        while let Some(Token::Operator(_) | Token::Negate | Token::Function(_) | Token::LeftParen) =
+            self.tokens.last()
+        {
+            self.tokens.pop();
+        }
+        // a lone number previews as itself
+        if self.tokens.len() < 2 {
+            return None;
+        }
+        let depth = self.tokens.iter().fold(0usize, |depth, token| match token {
+            Token::LeftParen => depth + 1,
+            Token::RightParen => depth.saturating_sub(1),
+            _ => depth,
+        });
+        self.tokens
+            .extend(std::iter::repeat_n(Token::RightParen, depth));
+        let result = self.evaluate_tokens().ok()?;
+        Some(self.format_quantity(&result))
+    }
+}
+
+/// Quantities with units, such as `5 km` or `60 km/h`.
+///
+/// A unit written after a number or group binds tighter than any operator,
+/// so `1 / 2 h` is half of one per hour and `60 km/h` is `(60 km) / h`.
+/// Sums need units of the same kind and are in the unit on the left;
+/// products keep the units, expressed in the first unit of each kind.
+/// Amounts of money are computed in decimal when the backend is `f64`.
+impl Engine {
+    /// The unit named by token `index`, if it is a unit and not a variable.
+    fn unit_at(&self, index: usize) -> Option<Unit> {
+        match self.tokens.get(index) {
+            Some(Token::Variable(name)) if !self.variables.contains_key(name) => {
+                Unit::named(name, &self.rates)
+            }
+            _ => None,
+        }
+    }
+
+    /// The backend for quantities in `unit`: money is counted in decimal
+    /// rather than `f64`, so that `0.1 USD + 0.2 USD` is `0.3 USD`.
+    fn backend_for(&self, unit: &Unit) -> Backend {
+        if unit.is_money() && self.backend == Backend::Float {
+            Backend::Decimal
+        } else {
+            self.backend
+        }
+    }
+
+    /// The unit named by variable token `index`, which is not a variable.
+    fn named_unit(&self, index: usize) -> Result<Unit, Error> {
+        let Some(Token::Variable(name)) = self.tokens.get(index) else {
+            return Err(Error::at(ErrorKind::InvalidExpression, index));
+        };
+        let unit =
+            Unit::named(name, &self.rates).ok_or(Error::at(ErrorKind::UnknownVariable, index))?;
+        if self.backend == Backend::Integer {
+            return Err(Error::at(ErrorKind::UnitsNeedNonInteger, index));
+        }
+        Ok(unit)
+    }
+
+    /// `quantity` with the unit at `pos`, if one follows, as in `5 km` or
+    /// `2 m^2`; the power belongs to the unit.
+    fn with_unit_suffix(&self, pos: &mut usize, quantity: Quantity) -> Result<Quantity, Error> {
+        let Some(Token::Variable(name)) = self.tokens.get(*pos) else {
+            return Ok(quantity);
+        };
+        if self.variables.contains_key(name) || Unit::named(name, &self.rates).is_none() {
+            return Ok(quantity);
+        }
+        let mut unit = self.named_unit(*pos)?;
+        *pos += 1;
+
+        let power = match self.tokens.get(*pos..) {
+            Some([Token::Operator(Operator::Power), Token::Number(power), ..]) => {
+                power.parse::<i32>().ok().map(|power| (power, 2))
+            }
+            Some(
+                [
+                    Token::Operator(Operator::Power),
+                    Token::Negate,
+                    Token::Number(power),
+                    ..,
+                ],
+            ) => power.parse::<i32>().ok().map(|power| (-power, 3)),
+            _ => None,
+        };
+        if let Some((power, tokens)) = power {
+            *pos += tokens;
+            unit = unit.powi(power).map_err(|kind| Error::at(kind, *pos - 1))?;
+        }
+
+        let index = *pos - 1;
+        let one = Value::parse("1", self.backend)?;
+        self.apply_quantities(quantity, Quantity { value: one, unit }, Operator::Multiply)
+            .map_err(|kind| Error::at(kind, index))
+    }
+
+    fn apply_quantities(
+        &self,
+        lhs: Quantity,
+        rhs: Quantity,
+        operator: Operator,
+    ) -> Result<Quantity, ErrorKind> {
+        let (lhs, rhs) = if lhs.unit.is_money() || rhs.unit.is_money() {
+            let backend = self.backend_for(if lhs.unit.is_money() {
+                &lhs.unit
+            } else {
+                &rhs.unit
+            });
+            (lhs.into_backend(backend), rhs.into_backend(backend))
+        } else {
+            (lhs, rhs)
+        };
+        if operator == Operator::To {
+            let value = self.convert(lhs, &rhs.unit, true)?;
+            return Ok(Quantity {
+                value,
+                unit: rhs.unit,
+            });
+        }
+        if lhs.unit.is_empty() && rhs.unit.is_empty() {
+            return Ok(self.apply_operator(lhs.value, rhs.value, operator)?.into());
+        }
+
+        match operator {
+            Operator::Add | Operator::Subtract | Operator::Modulo => {
+                let rhs = self.convert(rhs, &lhs.unit, false)?;
+                Ok(Quantity {
+                    value: self.apply_operator(lhs.value, rhs, operator)?,
+                    unit: lhs.unit,
+                })
+            }
+            // how many times one fits in the other, or how much one
+            // changed to the other, is a plain number
+            Operator::FloorDivide | Operator::PercentChange => {
+                let rhs = self.convert(rhs, &lhs.unit, false)?;
+                Ok(self.apply_operator(lhs.value, rhs, operator)?.into())
+            }
+            Operator::Multiply | Operator::Divide => {
+                let sign = if operator == Operator::Multiply {
+                    1
+                } else {
+                    -1
+                };
+                let (unit, conversion) = lhs.unit.combine(&rhs.unit, sign)?;
+                let mut value = self.apply_operator(lhs.value, rhs.value, operator)?;
+                if !conversion.is_empty() {
+                    value = self.scale(value, conversion.size_value())?;
+                }
+                self.simplify(Quantity { value, unit })
+            }
+            // a price in the unit of the cost
+            Operator::Markup | Operator::Margin if rhs.unit.is_empty() => Ok(Quantity {
+                value: self.apply_operator(lhs.value, rhs.value, operator)?,
+                unit: lhs.unit,
+            }),
+            Operator::Power if rhs.unit.is_empty() => {
+                let exponent = rhs.value.to_f64();
+                if exponent.fract() != 0.0 || exponent.abs() > f64::from(MAX_UNIT_POWER) {
+                    return Err(ErrorKind::IncompatibleUnits);
+                }
+                Ok(Quantity {
+                    unit: lhs.unit.powi(exponent as i32)?,
+                    value: self.apply_operator(lhs.value, rhs.value, operator)?,
+                })
+            }
+            _ => Err(ErrorKind::IncompatibleUnits),
+        }
+    }
+
+    /// Applies `function`, which takes plain numbers, apart from the square
+    /// root of a unit with even powers such as `m^2`.
+    fn apply_function(&self, operand: Quantity, function: Function) -> Result<Quantity, ErrorKind> {
+        let apply = |value: Value| {
+            value.apply_function(function, &self.decimal, self.angle_unit, self.float_mode)
+        };
+        if operand.unit.is_empty() {
+            return apply(operand.value).map(Quantity::from);
+        }
+        match operand.unit.sqrt() {
+            Some(unit) if function == Function::Sqrt => Ok(Quantity {
+                value: apply(operand.value)?,
+                unit,
+            }),
+            _ if operand.unit.is_dimensionless() => {
+                apply(self.simplify(operand)?.value).map(Quantity::from)
+            }
+            _ => Err(ErrorKind::IncompatibleUnits),
+        }
+    }
+
+    /// The value of `quantity` in unit `target`; `absolute` temperatures
+    /// are converted with their offsets.
+    fn convert(
+        &self,
+        quantity: Quantity,
+        target: &Unit,
+        absolute: bool,
+    ) -> Result<Value, ErrorKind> {
+        if quantity.unit == *target {
+            return Ok(quantity.value);
+        }
+        let (factor, offset) = quantity
+            .unit
+            .conversion_to(target, absolute)
+            .ok_or(ErrorKind::IncompatibleUnits)?;
+        let value = self.scale(quantity.value, factor)?;
+        match offset {
+            Some(offset) => {
+                let offset = offset.into_backend(value.backend());
+                self.apply_operator(value, offset, Operator::Add)
+            }
+            None => Ok(value),
+        }
+    }
+
+    /// `value` times an exact conversion `factor`, in the backend of
+    /// `value`.
+    fn scale(&self, value: Value, factor: Value) -> Result<Value, ErrorKind> {
+        let factor = factor.into_backend(value.backend());
+        self.apply_operator(value, factor, Operator::Multiply)
+    }
+
+    /// A quantity in a unit that measures nothing, like `km/m`, as the
+    /// plain number it stands for.
+    fn simplify(&self, quantity: Quantity) -> Result<Quantity, ErrorKind> {
+        if quantity.unit.is_empty() || !quantity.unit.is_dimensionless() {
+            return Ok(quantity);
+        }
+        let size = quantity.unit.size_value();
+        Ok(self.scale(quantity.value, size)?.into())
+    }
+}
+
+/// The last operator outside any parentheses and everything after it, such
+/// as `+ 4` in `2 × 3 + 4`.
+fn last_operation(tokens: &[Token]) -> Option<Vec<Token>> {
+    let mut depth = 0usize;
+    let mut last = None;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LeftParen => depth += 1,
+            Token::RightParen => depth = depth.saturating_sub(1),
+            Token::Operator(_) if depth == 0 => last = Some(index),
+            _ => {}
+        }
+    }
+    last.map(|index| tokens[index..].to_vec())
+}
+
+/// Splits text such as `5 km` into the number and its unit.
+fn split_quantity<'a>(text: &'a str, rates: &Rates) -> Option<(&'a str, Unit)> {
+    let (number, unit) = text.split_once(' ')?;
+    Some((number, Unit::parse(unit, rates)?))
+}
+
+/// Reverse Polish entry, HP style: numbers are pushed onto a stack with
+/// Enter and operators replace the top two values with their result.
+///
+/// The stack shares `input`, the backend settings and the arithmetic with
+/// the infix calculator, so a front end can offer both.
+impl Engine {
+    /// The stack, bottom first; the last value is the top.
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    /// The stack top while nothing is being typed or built infix.
+    fn stack_top(&self) -> Option<&Value> {
+        if self.input.is_empty() && self.tokens.is_empty() {
+            self.stack.last()
+        } else {
+            None
+        }
+    }
+
+    /// Pushes the number being typed, or duplicates the top when nothing is
+    /// typed.
+    pub fn rpn_enter(&mut self) -> Result<(), Error> {
+        if self.input.is_empty() {
+            return self.stack_dup();
+        }
+        self.push_input_to_stack()
+    }
+
+    fn push_input_to_stack(&mut self) -> Result<(), Error> {
+        if self.input.is_empty() {
+            return Ok(());
+        }
+        let value = self.parse_number(&self.input_literal())?;
+        let value = self.fit_integer(value);
+        self.stack.push(value);
+        self.input.clear();
+        self.just_evaluated = false;
+        Ok(())
+    }
+
+    /// Replaces the top two values `y`, `x` with `y operator x`, pushing
+    /// the number being typed first, so `3 Enter 4 +` gives `7`.
+    pub fn rpn_operator(&mut self, operator: Operator) -> Result<(), Error> {
+        self.push_input_to_stack()?;
+        let [lhs, rhs] = self.stack_operands()?;
+        self.wrapped.set(false);
+        let result = self.apply_operator(lhs, rhs, operator)?;
+        self.stack.truncate(self.stack.len() - 2);
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// Pushes the value of variable `name`, or of an earlier result for
+    /// `Ans` or `AnsN`.
+    pub fn rpn_push_variable(&mut self, name: &str) -> Result<(), Error> {
+        let quantity = match parse_answer(name) {
+            Some(back) => self.answer(back).ok_or(ErrorKind::NoAnswer)?,
+            None => self.variables.get(name).ok_or(ErrorKind::UnknownVariable)?,
+        };
+        // the stack holds plain numbers, so a unit cannot come along
+        if !quantity.unit.is_empty() {
+            return Err(ErrorKind::IncompatibleUnits.into());
+        }
+        let value = quantity.value.clone().into_backend(self.backend);
+        self.push_input_to_stack()?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Applies `function` to the top value.
+    pub fn rpn_function(&mut self, function: Function) -> Result<(), Error> {
+        self.push_input_to_stack()?;
+        let top = self.stack.last().cloned().ok_or(ErrorKind::StackEmpty)?;
+        let result = match (function, top.into_backend(self.backend)) {
+            (Function::Not, Value::Integer(value)) => Value::Integer(self.integer.not(value)),
+            (Function::Not, _) => return Err(ErrorKind::BitwiseNeedsInteger.into()),
+            (function, value) => self.fit_integer(value.apply_function(
+                function,
+                &self.decimal,
+                self.angle_unit,
+                self.float_mode,
+            )?),
+        };
+        *self.stack.last_mut().expect("checked above") = result;
+        Ok(())
+    }
+
+    /// The top two values as `[y, x]` in the active backend, without
+    /// removing them.
+    fn stack_operands(&self) -> Result<[Value; 2], Error> {
+        match self.stack.as_slice() {
+            [.., lhs, rhs] => Ok([
+                lhs.clone().into_backend(self.backend),
+                rhs.clone().into_backend(self.backend),
+            ]),
+            _ => Err(ErrorKind::StackNeedsTwoValues.into()),
+        }
+    }
+
+    /// Changes the sign of the number being typed, or else of the top value.
+    pub fn stack_negate(&mut self) {
+        if !self.input.is_empty() {
+            self.toggle_sign();
+        } else if let Some(top) = self.stack.pop() {
+            let negated = self.fit_integer(top.negate());
+            self.stack.push(negated);
+        }
+    }
+
+    /// Exchanges the top two values (x⇄y).
+    pub fn stack_swap(&mut self) -> Result<(), Error> {
+        self.push_input_to_stack()?;
+        let len = self.stack.len();
+        if len < 2 {
+            return Err(ErrorKind::StackNeedsTwoValues.into());
+        }
+        self.stack.swap(len - 1, len - 2);
+        Ok(())
+    }
+
+    /// Discards the number being typed, or else the top value.
+    pub fn stack_drop(&mut self) {
+        if self.input.is_empty() {
+            self.stack.pop();
+        } else {
+            self.input.clear();
+        }
+    }
+
+    /// Pushes a copy of the top value, after the number being typed.
+    pub fn stack_dup(&mut self) -> Result<(), Error> {
+        self.push_input_to_stack()?;
+        let top = self.stack.last().cloned().ok_or(ErrorKind::StackEmpty)?;
+        self.stack.push(top);
+        Ok(())
+    }
+
+    /// Rolls the stack down (R↓): the top value moves to the bottom.
+    pub fn stack_roll(&mut self) -> Result<(), Error> {
+        self.push_input_to_stack()?;
+        if let Some(top) = self.stack.pop() {
+            self.stack.insert(0, top);
+        }
+        Ok(())
+    }
+
+    pub fn stack_clear(&mut self) {
+        self.stack.clear();
+    }
+}
+
+/// Evaluates a complete expression with a fresh [`Engine`].
+pub fn evaluate_expression(expression: &str) -> Result<String, Error> {
+    Engine::new().evaluate_line(expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Notation;
+
+    fn push_number(engine: &mut Engine, number: &str) {
+        for ch in number.chars() {
+            engine.push_digit(ch);
+        }
+    }
+
+    #[test]
+    fn digit_entry_and_decimal_behavior() {
+        let mut engine = Engine::new();
+        engine.push_digit('0');
+        engine.push_digit('5');
+        assert_eq!(engine.input(), "5");
+
+        engine.push_decimal_point();
+        engine.push_digit('2');
+        assert_eq!(engine.input(), "5.2");
+
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_digit('1');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "6.2");
+        assert!(engine.just_evaluated());
+
+        engine.push_digit('3');
+        assert_eq!(engine.input(), "3");
+    }
+
+    #[test]
+    fn backspace_removes_last_digit() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "2000");
+
+        engine.backspace();
+        engine.backspace();
+        assert_eq!(engine.input(), "20");
+
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_digit('1');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "21");
+    }
+
+    #[test]
+    fn full_expression_respects_precedence() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "10");
+        engine.push_operator(Operator::Add).unwrap();
+        push_number(&mut engine, "10");
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.push_digit('5');
+        engine.push_operator(Operator::Divide).unwrap();
+        engine.push_digit('4');
+        engine.push_operator(Operator::Add).unwrap();
+        push_number(&mut engine, "45");
+
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "67.5");
+        assert!(engine.tokens().is_empty());
+    }
+
+    #[test]
+    fn divide_by_zero_is_an_error() {
+        let mut engine = Engine::new();
+        engine.push_digit('8');
+        engine.push_operator(Operator::Divide).unwrap();
+        engine.push_digit('0');
+
+        assert_eq!(
+            engine.evaluate(),
+            Err(Error::at(ErrorKind::DivideByZero, 1))
+        );
+        assert_eq!(engine.expression_line(), "8 ÷ 0", "kept for fixing");
+        assert_eq!(engine.expression_token_range(1), Some(2..4));
+
+        engine.backspace();
+        assert_eq!(engine.expression_line(), "8 ÷");
+        engine.push_digit('4');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "2");
+    }
+
+    #[test]
+    fn recall_replaces_pending_input() {
+        let mut engine = Engine::new();
+        engine.push_digit('2');
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_digit('9');
+        engine.recall("20");
+        assert_eq!(engine.expression_line(), "2 + 20");
+
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "22");
+    }
+
+    #[test]
+    fn memory_accumulates_displayed_values() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "12");
+        engine.memory_add().unwrap();
+        push_number(&mut engine, "5");
+        assert_eq!(engine.input(), "5");
+        engine.memory_add().unwrap();
+        engine.push_digit('2');
+        engine.memory_subtract().unwrap();
+        assert_eq!(engine.memory(), Some(&Value::Float(15.0)));
+
+        engine.clear();
+        engine.push_digit('3');
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.memory_recall();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "45");
+
+        engine.memory_clear();
+        assert_eq!(engine.memory(), None);
+    }
+
+    #[test]
+    fn variables_are_usable_as_operands() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "4");
+        engine.store_variable("width").unwrap();
+        assert_eq!(
+            engine.push_variable("height"),
+            Err(ErrorKind::UnknownVariable.into())
+        );
+
+        engine.clear();
+        engine.push_digit('3');
+        engine.push_variable("width").unwrap();
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_variable("width").unwrap();
+        assert_eq!(engine.expression_line(), "3 × width + width");
+
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "16");
+        assert_eq!(
+            engine.store_variable("2x"),
+            Err(ErrorKind::InvalidVariableName.into())
+        );
+    }
+
+    #[test]
+    fn percentages_depend_on_the_operator_before_them() {
+        let cases = [
+            ("200 + 10%", Backend::Float, "220"),
+            ("200 - 10%", Backend::Float, "180"),
+            ("200 × 10%", Backend::Float, "20"),
+            ("50 ÷ 200%", Backend::Float, "25"),
+            ("10%", Backend::Float, "0.1"),
+            ("-10%", Backend::Float, "-0.1"),
+            ("200 + -10%", Backend::Float, "180"),
+            ("200 - -10%", Backend::Float, "220"),
+            ("200 × -10%", Backend::Float, "-20"),
+            ("-2 ^ 2", Backend::Float, "-4"),
+            ("200 + 10% + 10%", Backend::Float, "242"),
+            ("200 + 10% - 10%", Backend::Float, "198"),
+            ("200 × 10% × 3", Backend::Float, "60"),
+            ("200 + 10% × 3", Backend::Float, "200.3"),
+            ("200 + (5 + 5)%", Backend::Float, "220"),
+            ("(200 + 10%) × 2", Backend::Float, "440"),
+            ("10% ^ 2", Backend::Decimal, "0.01"),
+            ("7 % 3", Backend::Float, "1"),
+            ("200 + 7 % 3", Backend::Float, "201"),
+            ("80 chg 100", Backend::Float, "25"),
+            ("100 chg 80", Backend::Float, "-20"),
+            ("80 markup 25", Backend::Float, "100"),
+            ("75 margin 25", Backend::Float, "100"),
+            ("1 + 80 markup 25", Backend::Float, "101"),
+            ("19.99 + 7.5%", Backend::Decimal, "21.48925"),
+            ("3 chg 4", Backend::Rational, "100/3"),
+            ("200 + 15%", Backend::Integer, "230"),
+            ("200 × 15%", Backend::Integer, "30"),
+            ("50 ÷ 200%", Backend::Integer, "25"),
+            ("80 chg 100", Backend::Integer, "25"),
+            ("75 margin 25", Backend::Integer, "100"),
+            ("200 km + 10%", Backend::Float, "220 km"),
+            ("1 km chg 1500 m", Backend::Float, "50"),
+            ("80 km markup 25", Backend::Float, "100 km"),
+        ];
+        for (line, backend, expected) in cases {
+            let mut engine = Engine::new();
+            engine.set_backend(backend);
+            assert_eq!(engine.evaluate_line(line), Ok(expected.into()), "{line}");
+        }
+
+        let mut engine = Engine::new();
+        assert_eq!(
+            engine.evaluate_line("75 margin 100"),
+            Err(Error::at(ErrorKind::DivideByZero, 1))
+        );
+        assert_eq!(
+            engine.evaluate_line("0 chg 5"),
+            Err(Error::at(ErrorKind::DivideByZero, 1))
+        );
+        assert_eq!(
+            engine.evaluate_line("2 m markup 1 m"),
+            Err(Error::at(ErrorKind::IncompatibleUnits, 2))
+        );
+    }
+
+    #[test]
+    fn percent_key_marks_a_percentage_or_a_remainder() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "200");
+        engine.push_operator(Operator::Add).unwrap();
+        push_number(&mut engine, "10");
+        engine.push_percent().unwrap();
+        engine.push_percent().unwrap();
+        assert_eq!(engine.expression_line(), "200 + 10%");
+        assert_eq!(engine.preview(), Some("220".into()));
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "220");
+
+        engine.push_percent().unwrap();
+        assert_eq!(engine.expression_line(), "Ans%");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "2.2");
+
+        push_number(&mut engine, "7");
+        engine.push_percent().unwrap();
+        push_number(&mut engine, "3");
+        assert_eq!(engine.expression_line(), "7 % 3");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "1");
+
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.push_percent().unwrap();
+        assert_eq!(engine.expression_line(), "Ans ×");
+    }
+
+    #[test]
+    fn repeated_evaluation_repeats_the_last_operation() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "5");
+        engine.push_operator(Operator::Add).unwrap();
+        push_number(&mut engine, "3");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "8");
+        assert_eq!(engine.repeat_line(), Some("Ans + 3".into()));
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "11");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "14");
+
+        engine.push_operator(Operator::Multiply).unwrap();
+        push_number(&mut engine, "1.5");
+        engine.evaluate().unwrap();
+        engine.evaluate().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "47.25");
+
+        // only the last operator and its operand repeat
+        engine.set_expression("2 × (1 + 1) - 1").unwrap();
+        engine.evaluate().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "2");
+        engine.set_expression("(1 + 2) × (3 - 1)").unwrap();
+        engine.evaluate().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "12");
+
+        engine.set_expression("200 + 10%").unwrap();
+        engine.evaluate().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "242");
+
+        engine.set_expression("2 km × 2").unwrap();
+        engine.evaluate().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.result_text(), "8 km");
+
+        // a new number starts afresh, and a lone number repeats nothing
+        push_number(&mut engine, "7");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.repeat_line(), None);
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "7");
+
+        engine.set_expression("1 + 1").unwrap();
+        engine.evaluate().unwrap();
+        engine.clear();
+        push_number(&mut engine, "5");
+        engine.evaluate().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "5");
+
+        // nor does a typed number once it is stored
+        engine.set_expression("5 + 3").unwrap();
+        engine.evaluate().unwrap();
+        push_number(&mut engine, "10");
+        engine.memory_add().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "10");
+        push_number(&mut engine, "4");
+        engine.store_variable("four").unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "4");
+    }
+
+    #[test]
+    fn ans_refers_to_earlier_results() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.push_variable("Ans"), Err(ErrorKind::NoAnswer.into()));
+        push_number(&mut engine, "2");
+        engine.push_operator(Operator::Add).unwrap();
+        push_number(&mut engine, "3");
+        engine.evaluate().unwrap();
+
+        engine.push_operator(Operator::Multiply).unwrap();
+        push_number(&mut engine, "2");
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_variable("Ans").unwrap();
+        assert_eq!(engine.expression_line(), "Ans × 2 + Ans");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "15");
+
+        engine.push_variable("Ans1").unwrap();
+        assert_eq!(engine.expression_line(), "Ans1");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "5");
+        push_number(&mut engine, "4");
+        assert_eq!(engine.expression_line(), "4");
+
+        engine.set_expression("Ans9 + 1").unwrap();
+        assert_eq!(engine.evaluate(), Err(Error::at(ErrorKind::NoAnswer, 0)));
+
+        // a repeated `+ Ans` adds the same amount each time
+        engine.set_expression("1 + Ans").unwrap();
+        engine.evaluate().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "11");
+
+        assert_eq!(engine.evaluate_line("5 km"), Ok("5 km".into()));
+        assert_eq!(engine.evaluate_line("Ans to m"), Ok("5000 m".into()));
+        assert_eq!(engine.evaluate_line("Ans1 + Ans"), Ok("10 km".into()));
+        assert_eq!(
+            engine.evaluate_line("Ans = 1"),
+            Err(ErrorKind::InvalidVariableName.into())
+        );
+    }
+
+    #[test]
+    fn evaluate_line_assigns_variables() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.evaluate_line("rate = 1.5 + 0.5"), Ok("2".into()));
+        assert_eq!(engine.evaluate_line("x = rate x 3"), Ok("6".into()));
+        assert_eq!(engine.evaluate_line("x + rate"), Ok("8".into()));
+        assert_eq!(engine.variables().len(), 2);
+        assert_eq!(engine.evaluate_line("xa = 2"), Ok("2".into()));
+        assert_eq!(engine.evaluate_line("3 x xa"), Ok("6".into()));
+        assert_eq!(engine.evaluate_line("(1 + 2)x xa"), Ok("6".into()));
+        assert_eq!(engine.evaluate_line("2x3"), Ok("6".into()));
+        assert_eq!(engine.evaluate_line("2 rate + 3 xa"), Ok("10".into()));
+
+        assert_eq!(
+            engine.evaluate_line("y + 1"),
+            Err(Error::at(ErrorKind::UnknownVariable, 0))
+        );
+        assert_eq!(
+            engine.evaluate_line("2a = 1"),
+            Err(ErrorKind::InvalidVariableName.into())
+        );
+    }
+
+    #[test]
+    fn variables_cannot_take_unit_names() {
+        let mut engine = Engine::new();
+        for name in ["s", "m", "h", "d", "g", "t", "in", "min"] {
+            assert_eq!(
+                engine.evaluate_line(&format!("{name} = 3")),
+                Err(ErrorKind::InvalidVariableName.into()),
+                "{name}"
+            );
+            assert_eq!(
+                engine.store_variable(name),
+                Err(ErrorKind::InvalidVariableName.into()),
+                "{name}"
+            );
+        }
+        assert_eq!(engine.evaluate_line("5 s"), Ok("5 s".into()));
+
+        let (rates, _) = Rates::parse("base = EUR\nUSD = 1.25\n");
+        engine.set_rates(rates);
+        assert_eq!(
+            engine.evaluate_line("USD = 2"),
+            Err(ErrorKind::InvalidVariableName.into())
+        );
+    }
+
+    #[test]
+    fn expression_text_reads_back_with_set_expression() {
+        let mut engine = integer_engine(8, true, Radix::Hex);
+        push_number(&mut engine, "F");
+        engine.push_function(Function::Not).unwrap();
+        engine.push_operator(Operator::Xor).unwrap();
+        push_number(&mut engine, "1F");
+        assert_eq!(engine.expression_line(), "not(0xF) xor 1F");
+        assert_eq!(engine.expression_text(), "not(0xF) xor 0x1F");
+
+        let text = engine.expression_text();
+        engine.set_expression(&text).unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "EF");
+
+        let mut engine = Engine::new();
+        engine.set_expression("2 × sqrt(16").unwrap();
+        engine.close_paren().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "8");
+        assert_eq!(
+            engine.set_expression("2 $ 3"),
+            Err(ErrorKind::UnexpectedCharacter.into())
+        );
+    }
+
+    #[test]
+    fn units_convert_and_check_dimensions() {
+        let mut engine = Engine::new();
+        let mut evaluate = |line: &str| engine.evaluate_line(line);
+        assert_eq!(evaluate("5 km to m"), Ok("5000 m".into()));
+        assert_eq!(evaluate("3 h × 60 km/h"), Ok("180 km".into()));
+        assert_eq!(evaluate("60 km/h × 30 min"), Ok("30 km".into()));
+        assert_eq!(evaluate("1 h + 30 min"), Ok("1.5 h".into()));
+        assert_eq!(evaluate("1 km / 1 m"), Ok("1000".into()));
+        assert_eq!(evaluate("36 km/h to m/s"), Ok("10 m/s".into()));
+        assert_eq!(evaluate("1 GiB to MiB"), Ok("1024 MiB".into()));
+        assert_eq!(evaluate("20 degC to degF"), Ok("68 degF".into()));
+        assert_eq!(evaluate("20 degC + 20 degC to K"), Ok("313.15 K".into()));
+        assert_eq!(evaluate("sqrt(16 m^2)"), Ok("4 m".into()));
+        assert_eq!(evaluate("2 m^2 to cm^2"), Ok("20000 cm^2".into()));
+        assert_eq!(evaluate("trip = 5 km"), Ok("5 km".into()));
+        assert_eq!(evaluate("trip / 2 h"), Ok("2.5 km/h".into()));
+
+        assert_eq!(
+            evaluate("1 m + 1 s"),
+            Err(Error::at(ErrorKind::IncompatibleUnits, 2))
+        );
+        assert_eq!(
+            evaluate("2 km to s"),
+            Err(Error::at(ErrorKind::IncompatibleUnits, 2))
+        );
+        assert_eq!(
+            evaluate("sin(5 m)"),
+            Err(Error::at(ErrorKind::IncompatibleUnits, 0))
+        );
+        assert_eq!(
+            evaluate("5 furlong"),
+            Err(Error::at(ErrorKind::UnknownVariable, 2))
+        );
+        assert_eq!(
+            evaluate("2 m^2147483647 * m"),
+            Err(Error::at(ErrorKind::OutOfRange, 3))
+        );
+        assert_eq!(
+            evaluate("1 km^999999999"),
+            Err(Error::at(ErrorKind::OutOfRange, 3))
+        );
+        assert_eq!(
+            evaluate("1 m^100 * 1 m^100"),
+            Err(Error::at(ErrorKind::OutOfRange, 4))
+        );
+        assert_eq!(
+            evaluate("(1 m^100) ^ 2"),
+            Err(Error::at(ErrorKind::OutOfRange, 6))
+        );
+
+        engine.set_backend(Backend::Decimal);
+        assert_eq!(
+            engine.evaluate_line("5 km to mi"),
+            Ok("3.106855961186669848087170921816591 mi".into())
+        );
+        engine.set_backend(Backend::Integer);
+        assert_eq!(
+            engine.evaluate_line("1 KiB to B"),
+            Err(Error::at(ErrorKind::UnitsNeedNonInteger, 1))
+        );
+    }
+
+    #[test]
+    fn currency_amounts_use_decimal_arithmetic() {
+        let mut engine = Engine::new();
+        let (rates, _) = Rates::parse("base = EUR\nUSD = 1.25\nJPY = 160\n");
+        engine.set_rates(rates);
+
+        assert_eq!(engine.evaluate_line("100 USD to EUR"), Ok("80 EUR".into()));
+        assert_eq!(
+            engine.evaluate_line("0.1 USD + 0.2 USD"),
+            Ok("0.3 USD".into())
+        );
+        assert_eq!(
+            engine.evaluate_line("3 × 19.99 USD"),
+            Ok("59.97 USD".into())
+        );
+        assert_eq!(
+            engine.evaluate_line("10 EUR + 1000 JPY"),
+            Ok("16.25 EUR".into())
+        );
+        assert_eq!(engine.evaluate_line("2 EUR/h × 30 min"), Ok("1 EUR".into()));
+        assert_eq!(
+            engine.evaluate_line("1 USD + 1 m"),
+            Err(Error::at(ErrorKind::IncompatibleUnits, 2))
+        );
+
+        engine.evaluate_line("price = 19.99 USD").unwrap();
+        assert_eq!(engine.evaluate_line("price × 3"), Ok("59.97 USD".into()));
+
+        engine.recall("33.33333333333333333333333333333333 EUR");
+        assert_eq!(engine.display(), "33.33333333333333333333333333333333");
+        assert_eq!(engine.format_text("1.10 EUR"), "1.1 EUR");
+
+        engine.set_rates(Rates::default());
+        assert_eq!(
+            engine.evaluate_line("1 USD"),
+            Err(Error::at(ErrorKind::UnknownVariable, 2))
+        );
+    }
+
+    #[test]
+    fn quantity_results_keep_their_unit() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "5");
+        engine.push_variable("km").unwrap();
+        assert_eq!(engine.expression_line(), "5 km");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "5");
+        assert_eq!(engine.result_unit().map(Unit::to_string), Some("km".into()));
+        assert_eq!(engine.result_text(), "5 km");
+
+        engine.push_operator(Operator::Multiply).unwrap();
+        push_number(&mut engine, "2");
+        assert_eq!(engine.expression_line(), "Ans × 2");
+        assert_eq!(engine.preview(), Some("10 km".into()));
+        engine.evaluate().unwrap();
+        assert_eq!(engine.result_text(), "10 km");
+
+        engine.recall("3.5 km/h");
+        assert_eq!(engine.format_text("3.5 km/h"), "3.5 km/h");
+        engine.push_operator(Operator::Add).unwrap();
+        push_number(&mut engine, "1");
+        engine.push_variable("mph").unwrap();
+        assert_eq!(engine.expression_line(), "(3.5 km ÷ h) + 1 mph");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.result_text(), "5.109344 km/h");
+
+        engine.store_variable("pace").unwrap();
+        push_number(&mut engine, "2");
+        engine.store_variable("two").unwrap();
+        assert_eq!(engine.result_unit(), None);
+        assert_eq!(
+            engine.evaluate_line("pace × 2 h"),
+            Ok("10.218688 km".into())
+        );
+    }
+
+    #[test]
+    fn number_format_reformats_the_result_losslessly() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "1234567");
+        engine.push_operator(Operator::Divide).unwrap();
+        push_number(&mut engine, "3");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "411522.3333333333");
+
+        let fixed = NumberFormat {
+            grouping: true,
+            decimals: Some(2),
+            ..NumberFormat::default()
+        };
+        engine.set_number_format(fixed);
+        assert_eq!(engine.display(), "411,522.33");
+        engine.set_number_format(NumberFormat {
+            notation: Notation::Scientific,
+            ..fixed
+        });
+        assert_eq!(engine.display(), "4.12e5");
+
+        engine.set_number_format(NumberFormat::default());
+        assert_eq!(engine.display(), "411522.3333333333");
+        engine.push_operator(Operator::Multiply).unwrap();
+        push_number(&mut engine, "3");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "1234567");
+
+        engine.set_float_mode(FloatMode::Ieee);
+        engine.set_number_format(fixed);
+        assert_eq!(engine.evaluate_line("0 / 0"), Ok("NaN".into()));
+    }
+
+    #[test]
+    fn float_mode_reports_or_keeps_non_finite_results() {
+        let mut engine = Engine::new();
+        assert_eq!(
+            engine.evaluate_line("10 ^ 308 × 10"),
+            Err(Error::at(ErrorKind::Overflow, 3))
+        );
+        assert_eq!(
+            engine.evaluate_line("1 / 0.00000000000000000001"),
+            Ok("100000000000000000000".into())
+        );
+
+        engine.set_float_mode(FloatMode::Ieee);
+        assert_eq!(engine.evaluate_line("1 / 0"), Ok("∞".into()));
+        assert_eq!(engine.evaluate_line("∞ - ∞"), Ok("NaN".into()));
+        push_number(&mut engine, "2");
+        engine.push_operator(Operator::Divide).unwrap();
+        push_number(&mut engine, "0");
+        engine.evaluate().unwrap();
+        engine.push_operator(Operator::Multiply).unwrap();
+        push_number(&mut engine, "2");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "∞", "an infinite result chains");
+    }
+
+    #[test]
+    fn preview_ignores_incomplete_tails_and_errors() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.preview(), None);
+        push_number(&mut engine, "12");
+        assert_eq!(engine.preview(), None);
+
+        engine.push_operator(Operator::Add).unwrap();
+        push_number(&mut engine, "3");
+        assert_eq!(engine.preview(), Some("15".into()));
+        engine.push_operator(Operator::Divide).unwrap();
+        assert_eq!(engine.preview(), Some("15".into()));
+        engine.open_paren().unwrap();
+        push_number(&mut engine, "2");
+        engine.push_operator(Operator::Subtract).unwrap();
+        assert_eq!(engine.preview(), Some("13.5".into()));
+        push_number(&mut engine, "2");
+        assert_eq!(engine.preview(), None, "division by zero waits for Enter");
+        assert_eq!(engine.expression_line(), "12 + 3 ÷ (2 - 2");
+
+        engine.clear();
+        assert_eq!(
+            engine.preview_expression("sqrt(16) + 2 ×"),
+            Some("6".into())
+        );
+        assert_eq!(engine.preview_expression("2 $"), None);
+
+        engine.evaluate_line("1 + 1").unwrap();
+        assert_eq!(engine.preview(), None);
+    }
+
+    #[test]
+    fn decimal_backend_applies_to_pending_input_and_memory() {
+        let mut engine = Engine::new();
+        engine.set_backend(Backend::Decimal);
+        engine.push_decimal_point();
+        engine.push_digit('1');
+        engine.memory_add().unwrap();
+        engine.push_decimal_point();
+        engine.push_digit('2');
+        engine.memory_add().unwrap();
+        engine.push_decimal_point();
+        engine.push_digit('3');
+        engine.memory_subtract().unwrap();
+        assert_eq!(engine.memory().map(Value::to_string), Some("0".into()));
+
+        engine.clear();
+        engine.push_decimal_point();
+        engine.push_digit('1');
+        engine.push_operator(Operator::Add).unwrap();
+        engine.set_backend(Backend::Float);
+        engine.push_decimal_point();
+        engine.push_digit('2');
+        engine.set_backend(Backend::Decimal);
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "0.3");
+    }
+
+    #[test]
+    fn rational_results_chain_exactly() {
+        let mut engine = Engine::new();
+        engine.set_backend(Backend::Rational);
+        engine.push_digit('1');
+        engine.push_operator(Operator::Divide).unwrap();
+        engine.push_digit('3');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "1/3");
+
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.push_digit('3');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "1");
+
+        assert_eq!(engine.evaluate_line("7 / 3"), Ok("7/3".into()));
+        engine.set_fraction_style(FractionStyle::Mixed);
+        assert_eq!(engine.evaluate_line("7 / 3"), Ok("2 1/3".into()));
+    }
+
+    #[test]
+    fn mixed_display_keeps_exact_result_for_chaining() {
+        let mut engine = Engine::new();
+        engine.set_backend(Backend::Rational);
+        engine.set_fraction_style(FractionStyle::Mixed);
+        engine.push_digit('7');
+        engine.push_operator(Operator::Divide).unwrap();
+        engine.push_digit('3');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "2 1/3");
+        assert_eq!(engine.input(), "7/3");
+
+        engine.memory_add().unwrap();
+        engine.push_operator(Operator::Subtract).unwrap();
+        engine.push_digit('2');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "1/3");
+        assert_eq!(engine.memory().map(Value::to_string), Some("7/3".into()));
+    }
+
+    #[test]
+    fn unary_minus_negates_the_next_operand() {
+        for (expression, expected) in [
+            ("2 - -3", "5"),
+            ("-5 × 3", "-15"),
+            ("3 × -2", "-6"),
+            ("-(2 + 3) × 2", "-10"),
+            ("--4", "4"),
+            ("2 × -(1 - 4)", "6"),
+            ("-2 - -2", "0"),
+        ] {
+            assert_eq!(
+                evaluate_expression(expression),
+                Ok(expected.to_string()),
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn minus_key_starts_negative_operands() {
+        let mut engine = Engine::new();
+        engine.push_operator(Operator::Subtract).unwrap();
+        engine.push_digit('5');
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.push_operator(Operator::Subtract).unwrap();
+        engine.open_paren().unwrap();
+        engine.push_digit('2');
+        engine.push_operator(Operator::Subtract).unwrap();
+        engine.push_operator(Operator::Subtract).unwrap();
+        engine.push_digit('3');
+        engine.close_paren().unwrap();
+        assert_eq!(engine.expression_line(), "-5 × -(2 - -3)");
+
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "25");
+    }
+
+    #[test]
+    fn minus_after_operator_toggles_and_other_operators_replace_it() {
+        let mut engine = Engine::new();
+        engine.push_digit('4');
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.push_operator(Operator::Subtract).unwrap();
+        assert_eq!(engine.expression_line(), "4 × -");
+        engine.push_operator(Operator::Subtract).unwrap();
+        assert_eq!(engine.expression_line(), "4 ×");
+
+        engine.push_operator(Operator::Subtract).unwrap();
+        engine.push_operator(Operator::Add).unwrap();
+        assert_eq!(engine.expression_line(), "4 +");
+    }
+
+    #[test]
+    fn toggle_sign_flips_input() {
+        let mut engine = Engine::new();
+        engine.push_digit('3');
+        engine.toggle_sign();
+        assert_eq!(engine.input(), "-3");
+        engine.toggle_sign();
+        assert_eq!(engine.input(), "3");
+
+        engine.clear();
+        engine.push_digit('0');
+        engine.toggle_sign();
+        engine.push_digit('7');
+        assert_eq!(engine.input(), "-7");
+        engine.backspace();
+        assert_eq!(engine.input(), "");
+
+        engine.clear();
+        engine.push_digit('2');
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.toggle_sign();
+        engine.push_digit('4');
+        assert_eq!(engine.expression_line(), "2 × -4");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "-8");
+
+        engine.toggle_sign();
+        assert_eq!(engine.display(), "8");
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_digit('1');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "9");
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        let mut engine = Engine::new();
+        engine.open_paren().unwrap();
+        engine.push_digit('2');
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_digit('3');
+        engine.close_paren().unwrap();
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.push_digit('4');
+        assert_eq!(engine.expression_line(), "(2 + 3) × 4");
+
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "20");
+    }
+
+    #[test]
+    fn nested_parentheses_evaluate_inside_out() {
+        let mut engine = Engine::new();
+        engine.push_digit('2');
+        engine.open_paren().unwrap();
+        engine.open_paren().unwrap();
+        engine.push_digit('1');
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_digit('2');
+        engine.close_paren().unwrap();
+        engine.push_operator(Operator::Multiply).unwrap();
+        engine.open_paren().unwrap();
+        engine.push_digit('6');
+        engine.push_operator(Operator::Subtract).unwrap();
+        engine.push_digit('2');
+        engine.close_paren().unwrap();
+        engine.close_paren().unwrap();
+        assert_eq!(engine.expression_line(), "2 × ((1 + 2) × (6 - 2))");
+
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "24");
+    }
+
+    #[test]
+    fn unbalanced_parentheses_are_an_error() {
+        let mut engine = Engine::new();
+        engine.open_paren().unwrap();
+        engine.push_digit('1');
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_digit('2');
+        assert_eq!(
+            engine.evaluate(),
+            Err(Error::at(ErrorKind::UnbalancedParentheses, 0))
+        );
+
+        engine.clear();
+        engine.push_digit('1');
+        engine.close_paren().unwrap();
+        assert_eq!(
+            engine.evaluate(),
+            Err(Error::at(ErrorKind::UnbalancedParentheses, 1))
+        );
+    }
+
+    #[test]
+    fn functions_wrap_the_pending_number_or_open_a_group() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "16");
+        engine.push_function(Function::Sqrt).unwrap();
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_function(Function::Ln).unwrap();
+        engine.push_digit('1');
+        assert_eq!(engine.expression_line(), "sqrt(16) + ln(1");
+
+        engine.close_paren().unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "4");
+
+        engine.push_function(Function::Exp).unwrap();
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), 4f64.exp().to_string());
+    }
+
+    #[test]
+    fn typed_functions_bind_tighter_than_operators() {
+        assert_eq!(evaluate_expression("sqrt 9 × 2"), Ok("6".into()));
+        assert_eq!(evaluate_expression("-sqrt(4) + log(100)"), Ok("0".into()));
+        assert_eq!(evaluate_expression("sin 0 + cos(0)"), Ok("1".into()));
+        assert_eq!(
+            evaluate_expression("sqrt(2 - 3)"),
+            Err(Error::at(ErrorKind::SquareRootOfNegative, 0))
+        );
+        assert_eq!(
+            Engine::from_expression("2 sqrt 4")
+                .unwrap()
+                .expression_line(),
+            "2 × sqrt 4"
+        );
+        assert_eq!(
+            Engine::new().evaluate_line("sqrt = 2"),
+            Err(ErrorKind::InvalidVariableName.into())
+        );
+    }
+
+    #[test]
+    fn precedence_and_associativity_table() {
+        let cases = [
+            ("2 ^ 3 ^ 2", "512"),
+            ("(2 ^ 3) ^ 2", "64"),
+            ("2 × 3 ^ 2", "18"),
+            ("-2 ^ 2", "-4"),
+            ("2 ^ -1", "0.5"),
+            ("7 // 2 × 3", "9"),
+            ("7 // (2 × 3)", "1"),
+            ("10 - 7 % 4", "7"),
+            ("7 % 4 × 2", "6"),
+            ("7 % -3", "-2"),
+            ("20 / 2 / 5", "2"),
+            ("8 - 4 - 2", "2"),
+            ("1 + 2 ^ 3 // 3", "3"),
+            ("sqrt 4 ^ 2", "4"),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(
+                evaluate_expression(expression).as_deref(),
+                Ok(expected),
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn double_slash_key_is_floor_division() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "7");
+        engine.push_operator(Operator::Divide).unwrap();
+        engine.push_operator(Operator::Divide).unwrap();
+        push_number(&mut engine, "2");
+        assert_eq!(engine.expression_line(), "7 // 2");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "3");
+    }
+
+    fn integer_engine(bits: u32, signed: bool, radix: Radix) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_backend(Backend::Integer);
+        engine.set_integer_settings(IntegerSettings {
+            bits,
+            signed,
+            radix,
+        });
+        engine
+    }
+
+    #[test]
+    fn integer_digits_follow_the_radix_and_wrap_visibly() {
+        let mut engine = integer_engine(8, false, Radix::Hex);
+        for digit in ['f', 'F', 'G', '.'] {
+            engine.push_digit(digit);
+        }
+        engine.push_decimal_point();
+        assert_eq!(engine.display(), "FF");
+
+        engine.push_operator(Operator::Add).unwrap();
+        engine.push_digit('1');
+        assert_eq!(engine.expression_line(), "0xFF + 1");
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "0");
+        assert!(engine.wrapped());
+
+        engine.push_digit('7');
+        engine.push_operator(Operator::ShiftLeft).unwrap();
+        engine.push_digit('1');
+        engine.evaluate().unwrap();
+        assert_eq!(engine.display(), "E");
+        assert!(!engine.wrapped());
+    }
+
+    #[test]
+    fn changing_radix_converts_pending_digits() {
+        let mut engine = integer_engine(16, true, Radix::Hex);
+        engine.push_digit('1');
+        engine.push_digit('F');
+        let mut settings = engine.integer_settings();
+        settings.radix = Radix::Bin;
+        engine.set_integer_settings(settings);
+        assert_eq!(engine.display(), "11111");
+
+        engine.set_backend(Backend::Float);
+        assert_eq!(engine.display(), "31");
+    }
+
+    #[test]
+    fn signed_words_read_radix_literals_as_bit_patterns() {
+        let mut engine = integer_engine(8, true, Radix::Dec);
+        assert_eq!(engine.evaluate_line("0xFF"), Ok("-1".into()));
+        assert!(!engine.wrapped());
+        assert_eq!(engine.evaluate_line("not 0 xor 0b101"), Ok("-6".into()));
+        assert_eq!(engine.evaluate_line("0x81 rol 1"), Ok("3".into()));
+        assert_eq!(engine.evaluate_line("-128 >> 7"), Ok("-1".into()));
+        assert_eq!(engine.evaluate_line("7 / 2"), Ok("3".into()));
+
+        assert_eq!(
+            evaluate_expression("6 & 3"),
+            Err(Error::at(ErrorKind::BitwiseNeedsInteger, 1))
+        );
+    }
+
+    #[test]
+    fn rpn_operators_take_the_top_two_values() {
+        let mut engine = Engine::new();
+        push_number(&mut engine, "3");
+        engine.rpn_enter().unwrap();
+        push_number(&mut engine, "4");
+        engine.rpn_operator(Operator::Add).unwrap();
+        assert_eq!(engine.display(), "7");
+
+        push_number(&mut engine, "2");
+        engine.rpn_operator(Operator::Power).unwrap();
+        push_number(&mut engine, "9");
+        engine.rpn_function(Function::Sqrt).unwrap();
+        engine.rpn_operator(Operator::Subtract).unwrap();
+        assert_eq!(engine.stack(), [Value::Float(46.0)]);
+
+        assert_eq!(
+            engine.rpn_operator(Operator::Divide),
+            Err(ErrorKind::StackNeedsTwoValues.into())
+        );
+        engine.rpn_enter().unwrap();
+        push_number(&mut engine, "0");
+        assert_eq!(
+            engine.rpn_operator(Operator::Divide),
+            Err(ErrorKind::DivideByZero.into())
+        );
+        assert_eq!(engine.stack().len(), 3, "operands survive an error");
+    }
+
+    #[test]
+    fn rpn_pushes_variables_without_units() {
+        let mut engine = Engine::new();
+        engine.evaluate_line("rate = 4").unwrap();
+        engine.evaluate_line("trip = 5 km").unwrap();
+        engine.rpn_push_variable("rate").unwrap();
+        assert_eq!(engine.stack(), [Value::Float(4.0)]);
+        assert_eq!(
+            engine.rpn_push_variable("trip"),
+            Err(ErrorKind::IncompatibleUnits.into())
+        );
+        assert_eq!(
+            engine.rpn_push_variable("Ans"),
+            Err(ErrorKind::IncompatibleUnits.into())
+        );
+        assert_eq!(engine.stack(), [Value::Float(4.0)]);
+    }
+
+    #[test]
+    fn rpn_stack_operations() {
+        let mut engine = Engine::new();
+        for number in ["1", "2", "3"] {
+            push_number(&mut engine, number);
+            engine.rpn_enter().unwrap();
+        }
+        let stack = |engine: &Engine| -> Vec<String> {
+            engine.stack().iter().map(Value::to_string).collect()
+        };
+
+        engine.stack_swap().unwrap();
+        assert_eq!(stack(&engine), ["1", "3", "2"]);
+        engine.stack_roll().unwrap();
+        assert_eq!(stack(&engine), ["2", "1", "3"]);
+        engine.stack_dup().unwrap();
+        assert_eq!(stack(&engine), ["2", "1", "3", "3"]);
+
+        push_number(&mut engine, "9");
+        engine.stack_drop();
+        assert_eq!(engine.input(), "");
+        engine.stack_drop();
+        assert_eq!(stack(&engine), ["2", "1", "3"]);
+
+        engine.clear();
+        assert_eq!(engine.display(), "3");
+        engine.stack_clear();
+        assert_eq!(engine.display(), "0");
+        assert_eq!(engine.stack_dup(), Err(ErrorKind::StackEmpty.into()));
+    }
+
+    #[test]
+    fn evaluate_expression_matches_interactive_rules() {
+        assert_eq!(
+            evaluate_expression("10 + 10 * 5 / 4 + 45"),
+            Ok("67.5".into())
+        );
+        assert_eq!(evaluate_expression("2(3 + 4)"), Ok("14".into()));
+        assert_eq!(evaluate_expression("7 : 2"), Ok("3.5".into()));
+
+        assert_eq!(
+            evaluate_expression("8 / 0"),
+            Err(Error::at(ErrorKind::DivideByZero, 1))
+        );
+        assert!(evaluate_expression("2 +").is_err());
+        assert!(evaluate_expression("(1 + 2").is_err());
+        assert!(evaluate_expression("   ").is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Why a calculation failed.
+
+use std::fmt;
+
+/// What went wrong, independent of where in the expression.
+///
+/// The [`Display`](fmt::Display) text is what the calculator shows after
+/// `Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    DivideByZero,
+    SquareRootOfNegative,
+    LogarithmOfZero,
+    LogarithmOfNegative,
+    InverseSineOutOfDomain,
+    InverseCosineOutOfDomain,
+    /// The tangent of an odd multiple of a right angle.
+    TangentUndefined,
+    FractionalPowerOfNegative,
+    /// A negative exponent in the integer backend.
+    NegativeExponent,
+    NegativeShift,
+    /// A result too large for the backend to hold.
+    OutOfRange,
+    /// An `f64` result that overflowed to infinity.
+    Overflow,
+    /// An `f64` result that is not a number, such as `0 × ∞`.
+    Undefined,
+    BitwiseNeedsInteger,
+    /// Quantities whose units measure different things, as in `1 m + 1 s`,
+    /// or a unit where only a plain number makes sense.
+    IncompatibleUnits,
+    UnitsNeedNonInteger,
+    InvalidNumber,
+    UnexpectedCharacter,
+    UnknownVariable,
+    /// An `Ans` or `AnsN` from before the first result.
+    NoAnswer,
+    InvalidVariableName,
+    UnbalancedParentheses,
+    InvalidExpression,
+    /// An expression that ends before its last operand.
+    IncompleteExpression,
+    EmptyExpression,
+    /// An RPN operation on an empty stack.
+    StackEmpty,
+    /// An RPN operation on a stack with fewer than two values.
+    StackNeedsTwoValues,
+}
+
+impl ErrorKind {
+    pub fn message(self) -> &'static str {
+        match self {
+            ErrorKind::DivideByZero => "Cannot divide by zero",
+            ErrorKind::SquareRootOfNegative => "square root of a negative number",
+            ErrorKind::LogarithmOfZero => "logarithm of zero",
+            ErrorKind::LogarithmOfNegative => "logarithm of a negative number",
+            ErrorKind::InverseSineOutOfDomain => "inverse sine of a value outside -1 to 1",
+            ErrorKind::InverseCosineOutOfDomain => "inverse cosine of a value outside -1 to 1",
+            ErrorKind::TangentUndefined => "tangent is undefined at odd multiples of a right angle",
+            ErrorKind::FractionalPowerOfNegative => "fractional power of a negative number",
+            ErrorKind::NegativeExponent => "negative exponent",
+            ErrorKind::NegativeShift => "negative shift amount",
+            ErrorKind::OutOfRange => "result out of range",
+            ErrorKind::Overflow => "result is too large",
+            ErrorKind::Undefined => "result is undefined",
+            ErrorKind::BitwiseNeedsInteger => "bitwise operators need the integer backend",
+            ErrorKind::IncompatibleUnits => "incompatible units",
+            ErrorKind::UnitsNeedNonInteger => "units need the f64, decimal or rational backend",
+            ErrorKind::InvalidNumber => "invalid number",
+            ErrorKind::UnexpectedCharacter => "unexpected character in expression",
+            ErrorKind::UnknownVariable => "unknown variable",
+            ErrorKind::NoAnswer => "no such earlier result",
+            ErrorKind::InvalidVariableName => "invalid variable name",
+            ErrorKind::UnbalancedParentheses => "unbalanced parentheses",
+            ErrorKind::InvalidExpression => "invalid expression",
+            ErrorKind::IncompleteExpression => "incomplete expression",
+            ErrorKind::EmptyExpression => "empty expression",
+            ErrorKind::StackEmpty => "stack is empty",
+            ErrorKind::StackNeedsTwoValues => "stack needs two values",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// A failed calculation, with the index of the token it is about when
+/// there is one, so that a front end can point at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Index into the engine's tokens of the offending number, operator,
+    /// function or parenthesis.
+    pub token: Option<usize>,
+}
+
+impl Error {
+    /// An error about the token at `index`.
+    pub fn at(kind: ErrorKind, index: usize) -> Self {
+        Self {
+            kind,
+            token: Some(index),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self { kind, token: None }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl std::error::Error for Error {}
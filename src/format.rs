@@ -0,0 +1,262 @@
+//! How numbers are written out: digit grouping, fixed decimal places and
+//! scientific or engineering notation.
+//!
+//! A [`NumberFormat`] works on the exact text of a value, so changing it
+//! only changes what is shown, never a stored result.
+
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, RoundingMode};
+use num_traits::{Signed, Zero};
+
+/// Most decimal places a fixed format can show.
+pub const MAX_FIXED_DECIMALS: u32 = 15;
+
+/// [`Notation::Auto`] shows magnitudes from `10^12` up in scientific
+/// notation.
+pub const AUTO_SCIENTIFIC_FROM: i64 = 12;
+
+/// [`Notation::Auto`] shows nonzero magnitudes below `10^-6` in scientific
+/// notation.
+pub const AUTO_SCIENTIFIC_BELOW: i64 = -6;
+
+/// How the magnitude of a number is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// Every digit in place: `1234567.5`.
+    #[default]
+    Plain,
+    /// Plain, but scientific for magnitudes outside
+    /// [`AUTO_SCIENTIFIC_BELOW`] to [`AUTO_SCIENTIFIC_FROM`].
+    Auto,
+    /// One digit before the point: `1.2345675e6`.
+    Scientific,
+    /// Exponents in multiples of three, to read off SI prefixes:
+    /// `12.5e-3`.
+    Engineering,
+}
+
+impl Notation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Notation::Plain => "plain",
+            Notation::Auto => "auto",
+            Notation::Scientific => "sci",
+            Notation::Engineering => "eng",
+        }
+    }
+
+    pub fn next(self) -> Notation {
+        match self {
+            Notation::Plain => Notation::Auto,
+            Notation::Auto => Notation::Scientific,
+            Notation::Scientific => Notation::Engineering,
+            Notation::Engineering => Notation::Plain,
+        }
+    }
+}
+
+/// Display settings for the `f64`, decimal and rational backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub notation: Notation,
+    /// Separates thousands with commas: `1,234,567.5`.
+    pub grouping: bool,
+    /// Rounds to exactly this many decimal places, of the mantissa in
+    /// scientific and engineering notation; `None` shows every digit.
+    pub decimals: Option<u32>,
+}
+
+impl NumberFormat {
+    /// Formats `text`, a plain decimal as a [`Value`](crate::Value)
+    /// prints, rounding fixed decimals with `rounding`. Other text, such
+    /// as `7/3` or `∞`, is returned unchanged.
+    pub fn apply(&self, text: &str, rounding: RoundingMode) -> String {
+        let Ok(value) = BigDecimal::from_str(text) else {
+            return text.to_string();
+        };
+
+        let magnitude = magnitude(&value);
+        let step = match self.notation {
+            Notation::Plain => None,
+            Notation::Auto
+                if magnitude.is_some_and(|magnitude| {
+                    !(AUTO_SCIENTIFIC_BELOW..AUTO_SCIENTIFIC_FROM).contains(&magnitude)
+                }) =>
+            {
+                Some(1)
+            }
+            Notation::Auto => None,
+            Notation::Scientific => Some(1),
+            Notation::Engineering => Some(3),
+        };
+        match step {
+            Some(step) => self.scientific(&value, magnitude.unwrap_or(0), step, rounding),
+            None => {
+                let plain = match self.decimals {
+                    Some(places) => value
+                        .with_scale_round(places.into(), rounding)
+                        .to_plain_string(),
+                    None => text.to_string(),
+                };
+                if self.grouping {
+                    group_thousands(&plain)
+                } else {
+                    plain
+                }
+            }
+        }
+    }
+
+    /// `value` as a mantissa and an exponent that is a multiple of `step`.
+    fn scientific(
+        &self,
+        value: &BigDecimal,
+        magnitude: i64,
+        step: i64,
+        rounding: RoundingMode,
+    ) -> String {
+        let mut exponent = magnitude.div_euclid(step) * step;
+        let mut mantissa = shift(value, -exponent);
+        match self.decimals {
+            Some(places) => {
+                mantissa = mantissa.with_scale_round(places.into(), rounding);
+                // rounding can carry into another digit, as in 9.99 → 10.0
+                if mantissa.abs() >= shift(&BigDecimal::from(1), step) {
+                    exponent += step;
+                    mantissa = shift(&mantissa, -step).with_scale(places.into());
+                }
+            }
+            None => mantissa = mantissa.normalized(),
+        }
+        format!("{}e{exponent}", mantissa.to_plain_string())
+    }
+}
+
+/// The power of ten of the leading digit of `value`, as in `2` for `512`
+/// and `-3` for `0.005`; `None` for zero.
+fn magnitude(value: &BigDecimal) -> Option<i64> {
+    if value.is_zero() {
+        return None;
+    }
+    let (digits, scale) = value.as_bigint_and_exponent();
+    let count = digits.abs().to_string().len() as i64;
+    Some(count - 1 - scale)
+}
+
+/// `value × 10^power`, exactly.
+fn shift(value: &BigDecimal, power: i64) -> BigDecimal {
+    let (digits, scale) = value.as_bigint_and_exponent();
+    BigDecimal::new(digits, scale - power)
+}
+
+/// Inserts a comma between each group of three digits before the point.
+fn group_thousands(text: &str) -> String {
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", text),
+    };
+    let (whole, fraction) = match unsigned.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (unsigned, None),
+    };
+
+    let mut grouped = String::from(sign);
+    for (index, digit) in whole.chars().enumerate() {
+        if index > 0 && (whole.len() - index) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        grouped.push('.');
+        grouped.push_str(fraction);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(notation: Notation, grouping: bool, decimals: Option<u32>, text: &str) -> String {
+        let format = NumberFormat {
+            notation,
+            grouping,
+            decimals,
+        };
+        format.apply(text, RoundingMode::HalfUp)
+    }
+
+    #[test]
+    fn plain_numbers_group_and_round() {
+        assert_eq!(
+            format(Notation::Plain, false, None, "1234567.5"),
+            "1234567.5"
+        );
+        assert_eq!(
+            format(Notation::Plain, true, None, "1234567.5"),
+            "1,234,567.5"
+        );
+        assert_eq!(format(Notation::Plain, true, None, "-123456"), "-123,456");
+        assert_eq!(
+            format(Notation::Plain, true, None, "999.12345"),
+            "999.12345"
+        );
+        assert_eq!(format(Notation::Plain, false, Some(2), "2.675"), "2.68");
+        assert_eq!(format(Notation::Plain, false, Some(2), "3"), "3.00");
+        assert_eq!(format(Notation::Plain, true, Some(0), "-9999.5"), "-10,000");
+        assert_eq!(format(Notation::Plain, false, Some(2), "-0.001"), "0.00");
+        assert_eq!(format(Notation::Plain, true, Some(2), "7/3"), "7/3");
+        assert_eq!(format(Notation::Plain, true, Some(2), "∞"), "∞");
+    }
+
+    #[test]
+    fn scientific_and_engineering_notation() {
+        assert_eq!(
+            format(Notation::Scientific, false, None, "1234567.5"),
+            "1.2345675e6"
+        );
+        assert_eq!(
+            format(Notation::Scientific, false, None, "-0.00025"),
+            "-2.5e-4"
+        );
+        assert_eq!(
+            format(Notation::Scientific, false, Some(2), "1234567.5"),
+            "1.23e6"
+        );
+        assert_eq!(
+            format(Notation::Scientific, false, Some(1), "9.96"),
+            "1.0e1"
+        );
+        assert_eq!(format(Notation::Scientific, false, None, "0"), "0e0");
+        assert_eq!(
+            format(Notation::Engineering, false, None, "1234567.5"),
+            "1.2345675e6"
+        );
+        assert_eq!(
+            format(Notation::Engineering, false, None, "0.0125"),
+            "12.5e-3"
+        );
+        assert_eq!(
+            format(Notation::Engineering, false, None, "-45000"),
+            "-45e3"
+        );
+        assert_eq!(
+            format(Notation::Engineering, false, Some(1), "999.96"),
+            "1.0e3"
+        );
+    }
+
+    #[test]
+    fn auto_notation_switches_at_the_thresholds() {
+        assert_eq!(
+            format(Notation::Auto, true, None, "999999999999"),
+            "999,999,999,999"
+        );
+        assert_eq!(format(Notation::Auto, true, None, "1000000000000"), "1e12");
+        assert_eq!(format(Notation::Auto, false, None, "0.000001"), "0.000001");
+        assert_eq!(format(Notation::Auto, false, None, "0.0000001"), "1e-7");
+        assert_eq!(format(Notation::Auto, false, Some(3), "0"), "0.000");
+    }
+}
@@ -0,0 +1,255 @@
+//! Calculation history and its on-disk store.
+//!
+//! The history file is line oriented, one calculation per line, oldest
+//! first. Each line holds three tab-separated fields:
+//!
+//! ```text
+//! <unix timestamp in seconds>\t<expression>\t<result>
+//! ```
+//!
+//! for example `1760486400\t(2 + 3) × 4\t20`. Lines that do not have this
+//! shape are skipped with a warning when the file is loaded.
+
+use std::{
+    env, fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Number of entries kept when no other limit is configured.
+pub const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
+/// One evaluated expression and its formatted result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Seconds since the Unix epoch at which the calculation was made.
+    pub timestamp: u64,
+    pub expression: String,
+    pub result: String,
+}
+
+/// Past calculations, oldest first, capped at `limit` entries.
+#[derive(Debug, Clone)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::with_limit(DEFAULT_HISTORY_LIMIT)
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            limit,
+        }
+    }
+
+    pub fn push(&mut self, expression: impl Into<String>, result: impl Into<String>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        self.push_entry(HistoryEntry {
+            timestamp,
+            expression: expression.into(),
+            result: result.into(),
+        });
+    }
+
+    /// Appends `entry`, dropping the oldest entries beyond the limit.
+    pub fn push_entry(&mut self, entry: HistoryEntry) {
+        self.entries.push(entry);
+        let excess = self.entries.len().saturating_sub(self.limit);
+        self.entries.drain(..excess);
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, index: usize) -> Option<&HistoryEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Reads and writes [`History`] in the file format described in the module
+/// documentation.
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+    path: PathBuf,
+    limit: usize,
+}
+
+impl HistoryStore {
+    pub fn new(path: impl Into<PathBuf>, limit: usize) -> Self {
+        Self {
+            path: path.into(),
+            limit,
+        }
+    }
+
+    /// `$XDG_DATA_HOME/calculator_cli/history`, falling back to
+    /// `~/.local/share` when `XDG_DATA_HOME` is unset or not absolute.
+    pub fn default_path() -> Option<PathBuf> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+        Some(data_home.join("calculator_cli").join("history"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the history, keeping the newest `limit` entries.
+    ///
+    /// A missing file is an empty history. Malformed lines are skipped and
+    /// described in the returned warnings.
+    pub fn load(&self) -> io::Result<(History, Vec<String>)> {
+        let mut history = History::with_limit(self.limit);
+        let mut warnings = Vec::new();
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok((history, warnings)),
+            Err(err) => return Err(err),
+        };
+
+        for (index, line) in contents.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            match parse_line(line) {
+                Some(entry) => history.push_entry(entry),
+                None => warnings.push(format!(
+                    "{}: skipping malformed history line {}",
+                    self.path.display(),
+                    index + 1
+                )),
+            }
+        }
+        Ok((history, warnings))
+    }
+
+    /// Replaces the file with the newest `limit` entries of `history`.
+    pub fn save(&self, history: &History) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let skip = history.len().saturating_sub(self.limit);
+        let mut contents = String::new();
+        for entry in &history.entries()[skip..] {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\n",
+                entry.timestamp, entry.expression, entry.result
+            ));
+        }
+        fs::write(&self.path, contents)
+    }
+}
+
+fn parse_line(line: &str) -> Option<HistoryEntry> {
+    let mut fields = line.split('\t');
+    let timestamp = fields.next()?.parse().ok()?;
+    let expression = fields.next().filter(|field| !field.is_empty())?;
+    let result = fields.next().filter(|field| !field.is_empty())?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(HistoryEntry {
+        timestamp,
+        expression: expression.into(),
+        result: result.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn temp_store(dir: &TempDir, limit: usize) -> HistoryStore {
+        HistoryStore::new(dir.path().join("history"), limit)
+    }
+
+    #[test]
+    fn push_drops_oldest_beyond_limit() {
+        let mut history = History::with_limit(2);
+        history.push("1 + 1", "2");
+        history.push("2 + 2", "4");
+        history.push("3 + 3", "6");
+
+        let results: Vec<&str> = history
+            .entries()
+            .iter()
+            .map(|e| e.result.as_str())
+            .collect();
+        assert_eq!(results, ["4", "6"]);
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let dir = TempDir::new("round-trip");
+        let store = temp_store(&dir, 10);
+        let mut history = History::new();
+        history.push("(2 + 3) × 4", "20");
+        history.push("7 ÷ 2", "3.5");
+
+        store.save(&history).unwrap();
+        let (loaded, warnings) = store.load().unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(loaded.entries(), history.entries());
+    }
+
+    #[test]
+    fn load_skips_malformed_lines_and_applies_limit() {
+        let dir = TempDir::new("malformed");
+        let store = temp_store(&dir, 2);
+        fs::write(
+            store.path(),
+            "100\t1 + 1\t2\nnot a timestamp\t2 + 2\t4\n\n200\t3 + 3\t6\n300\t4 + 4\n400\t5 + 5\t10\n",
+        )
+        .unwrap();
+
+        let (history, warnings) = store.load().unwrap();
+
+        let results: Vec<&str> = history
+            .entries()
+            .iter()
+            .map(|e| e.result.as_str())
+            .collect();
+        assert_eq!(results, ["6", "10"]);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("line 2"));
+        assert!(warnings[1].contains("line 5"));
+    }
+
+    #[test]
+    fn load_of_missing_file_is_empty() {
+        let dir = TempDir::new("missing");
+        let store = temp_store(&dir, 10);
+        let (history, warnings) = store.load().unwrap();
+        assert!(history.is_empty());
+        assert!(warnings.is_empty());
+    }
+}
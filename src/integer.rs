@@ -0,0 +1,329 @@
+//! Fixed-width integer arithmetic behind [`Backend::Integer`](crate::Backend).
+//!
+//! Values are held as `i128` in the range of the configured word: `0` to
+//! `2^bits - 1` when unsigned, `-2^(bits-1)` to `2^(bits-1) - 1` when
+//! signed. Every operation wraps its result back into that range the way
+//! the hardware would and reports whether it had to.
+
+use crate::{error::ErrorKind, token::Operator};
+
+/// Word sizes the integer backend can be set to, in bits.
+pub const WORD_SIZES: [u32; 4] = [8, 16, 32, 64];
+
+/// Base used to type and show integers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Radix {
+    #[default]
+    Dec,
+    Hex,
+    Oct,
+    Bin,
+}
+
+impl Radix {
+    pub const ALL: [Radix; 4] = [Radix::Hex, Radix::Dec, Radix::Oct, Radix::Bin];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Radix::Dec => "dec",
+            Radix::Hex => "hex",
+            Radix::Oct => "oct",
+            Radix::Bin => "bin",
+        }
+    }
+
+    pub fn next(self) -> Radix {
+        match self {
+            Radix::Dec => Radix::Hex,
+            Radix::Hex => Radix::Oct,
+            Radix::Oct => Radix::Bin,
+            Radix::Bin => Radix::Dec,
+        }
+    }
+
+    pub fn base(self) -> u32 {
+        match self {
+            Radix::Dec => 10,
+            Radix::Hex => 16,
+            Radix::Oct => 8,
+            Radix::Bin => 2,
+        }
+    }
+
+    /// Prefix of a literal in this radix, as in `0xFF`; empty for decimal.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Radix::Dec => "",
+            Radix::Hex => "0x",
+            Radix::Oct => "0o",
+            Radix::Bin => "0b",
+        }
+    }
+
+    /// The radix whose [`Radix::prefix`] starts `text`, and the rest of it.
+    pub fn strip_prefix(text: &str) -> Option<(Radix, &str)> {
+        [Radix::Hex, Radix::Oct, Radix::Bin]
+            .into_iter()
+            .find_map(|radix| Some((radix, text.strip_prefix(radix.prefix())?)))
+    }
+}
+
+/// Word size, signedness and radix used by the integer backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegerSettings {
+    /// One of [`WORD_SIZES`].
+    pub bits: u32,
+    pub signed: bool,
+    pub radix: Radix,
+}
+
+impl Default for IntegerSettings {
+    fn default() -> Self {
+        Self {
+            bits: 64,
+            signed: true,
+            radix: Radix::Dec,
+        }
+    }
+}
+
+impl IntegerSettings {
+    /// Short description such as `i32` or `u8`.
+    pub fn word_name(&self) -> String {
+        format!("{}{}", if self.signed { 'i' } else { 'u' }, self.bits)
+    }
+
+    fn mask(&self) -> u128 {
+        (1u128 << self.bits) - 1
+    }
+
+    /// The bit pattern of `value` within the word.
+    fn bit_pattern(&self, value: i128) -> u128 {
+        value as u128 & self.mask()
+    }
+
+    /// Reads a bit pattern of the word as a value, sign-extending it when
+    /// signed.
+    pub fn from_bits(&self, bits: u128) -> i128 {
+        let bits = bits & self.mask();
+        if self.signed && bits >> (self.bits - 1) & 1 == 1 {
+            bits as i128 - (1i128 << self.bits)
+        } else {
+            bits as i128
+        }
+    }
+
+    /// Wraps `value` into the word, returning the wrapped value and whether
+    /// it differs from `value`.
+    pub fn wrap(&self, value: i128) -> (i128, bool) {
+        let wrapped = self.from_bits(self.bit_pattern(value));
+        (wrapped, wrapped != value)
+    }
+
+    /// Combines two in-range values, returning the wrapped result and
+    /// whether it overflowed the word.
+    pub fn apply(
+        &self,
+        lhs: i128,
+        rhs: i128,
+        operator: Operator,
+    ) -> Result<(i128, bool), ErrorKind> {
+        let divides = matches!(
+            operator,
+            Operator::Divide | Operator::Modulo | Operator::FloorDivide
+        );
+        if divides && rhs == 0 {
+            return Err(ErrorKind::DivideByZero);
+        }
+
+        // `Err` holds a wrapped result when the exact one does not even fit
+        // in an i128; its low bits are still right
+        let exact = match operator {
+            Operator::Add => lhs.checked_add(rhs).ok_or(lhs.wrapping_add(rhs)),
+            Operator::Subtract => lhs.checked_sub(rhs).ok_or(lhs.wrapping_sub(rhs)),
+            Operator::Multiply => lhs.checked_mul(rhs).ok_or(lhs.wrapping_mul(rhs)),
+            // truncates toward zero like integer division in C and Rust
+            Operator::Divide => Ok(lhs / rhs),
+            Operator::FloorDivide => Ok(floor_div(lhs, rhs)),
+            Operator::Modulo => Ok(lhs - rhs * floor_div(lhs, rhs)),
+            Operator::Power => {
+                let exponent = u32::try_from(rhs).map_err(|_| ErrorKind::NegativeExponent)?;
+                lhs.checked_pow(exponent).ok_or(lhs.wrapping_pow(exponent))
+            }
+            Operator::And => Ok(lhs & rhs),
+            Operator::Or => Ok(lhs | rhs),
+            Operator::Xor => Ok(lhs ^ rhs),
+            Operator::ShiftLeft => {
+                let amount = shift_amount(rhs)?;
+                let shifted = self.bit_pattern(lhs).checked_shl(amount).unwrap_or(0);
+                let result = self.from_bits(shifted);
+                let exact = 1i128
+                    .checked_shl(amount)
+                    .and_then(|factor| lhs.checked_mul(factor));
+                return Ok((result, exact != Some(result)));
+            }
+            Operator::ShiftRight => {
+                // arithmetic for signed words, logical for unsigned ones,
+                // which holds because unsigned values are never negative
+                let amount = shift_amount(rhs)?;
+                Ok(lhs >> amount.min(127))
+            }
+            // conversions are between units, which integers do not have
+            Operator::To => return Err(ErrorKind::IncompatibleUnits),
+            // built by the engine from the operators above
+            Operator::PercentChange | Operator::Markup | Operator::Margin => {
+                return Err(ErrorKind::InvalidExpression);
+            }
+            Operator::RotateLeft | Operator::RotateRight => {
+                let bits = self.bit_pattern(lhs);
+                let mut amount = rhs.rem_euclid(self.bits as i128) as u32;
+                if operator == Operator::RotateRight {
+                    amount = (self.bits - amount) % self.bits;
+                }
+                let rotated = if amount == 0 {
+                    bits
+                } else {
+                    (bits << amount | bits >> (self.bits - amount)) & self.mask()
+                };
+                return Ok((self.from_bits(rotated), false));
+            }
+        };
+
+        Ok(match exact {
+            Ok(value) => self.wrap(value),
+            Err(low_bits) => (self.wrap(low_bits).0, true),
+        })
+    }
+
+    /// Bitwise NOT within the word.
+    pub fn not(&self, value: i128) -> i128 {
+        self.from_bits(!self.bit_pattern(value))
+    }
+
+    /// `value` in `radix` without a prefix. Decimal keeps the sign; the
+    /// other radices show the two's complement bit pattern of the word, as
+    /// in `FF` for `-1` in an 8-bit word.
+    pub fn format(&self, value: i128, radix: Radix) -> String {
+        let bits = self.bit_pattern(value);
+        match radix {
+            Radix::Dec => value.to_string(),
+            Radix::Hex => format!("{bits:X}"),
+            Radix::Oct => format!("{bits:o}"),
+            Radix::Bin => format!("{bits:b}"),
+        }
+    }
+
+    /// `value` as signed digits in the active radix, such as `-1F`, for
+    /// typing it back in.
+    pub fn digits(&self, value: i128) -> String {
+        let sign = if value < 0 { "-" } else { "" };
+        let magnitude = value.unsigned_abs();
+        let digits = match self.radix {
+            Radix::Dec => magnitude.to_string(),
+            Radix::Hex => format!("{magnitude:X}"),
+            Radix::Oct => format!("{magnitude:o}"),
+            Radix::Bin => format!("{magnitude:b}"),
+        };
+        format!("{sign}{digits}")
+    }
+
+    /// `value` as a literal in the active radix, such as `0x1F` or `-0x1F`.
+    pub fn literal(&self, value: i128) -> String {
+        let digits = self.digits(value);
+        match digits.strip_prefix('-') {
+            Some(digits) => format!("-{}{digits}", self.radix.prefix()),
+            None => format!("{}{digits}", self.radix.prefix()),
+        }
+    }
+}
+
+fn floor_div(lhs: i128, rhs: i128) -> i128 {
+    let quotient = lhs / rhs;
+    if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+fn shift_amount(rhs: i128) -> Result<u32, ErrorKind> {
+    u32::try_from(rhs)
+        .map(|amount| amount.min(128))
+        .map_err(|_| ErrorKind::NegativeShift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(bits: u32, signed: bool) -> IntegerSettings {
+        IntegerSettings {
+            bits,
+            signed,
+            radix: Radix::Dec,
+        }
+    }
+
+    #[test]
+    fn arithmetic_wraps_and_reports_it() {
+        let u8 = word(8, false);
+        assert_eq!(u8.apply(200, 100, Operator::Add), Ok((44, true)));
+        assert_eq!(u8.apply(0, 1, Operator::Subtract), Ok((255, true)));
+        assert_eq!(u8.apply(15, 17, Operator::Multiply), Ok((255, false)));
+
+        let i8 = word(8, true);
+        assert_eq!(i8.apply(127, 1, Operator::Add), Ok((-128, true)));
+        assert_eq!(i8.apply(-128, -1, Operator::Divide), Ok((-128, true)));
+        assert_eq!(i8.apply(-7, 2, Operator::Divide), Ok((-3, false)));
+        assert_eq!(i8.apply(-7, 2, Operator::FloorDivide), Ok((-4, false)));
+        assert_eq!(i8.apply(-7, 2, Operator::Modulo), Ok((1, false)));
+
+        let u64 = word(64, false);
+        let max = u64::MAX as i128;
+        assert_eq!(u64.apply(max, max, Operator::Multiply), Ok((1, true)));
+        assert_eq!(u64.apply(2, 64, Operator::Power), Ok((0, true)));
+        assert_eq!(
+            u64.apply(1, 0, Operator::Modulo),
+            Err(ErrorKind::DivideByZero)
+        );
+    }
+
+    #[test]
+    fn bitwise_shift_and_rotate() {
+        let u8 = word(8, false);
+        assert_eq!(u8.apply(0b1100, 0b1010, Operator::And), Ok((0b1000, false)));
+        assert_eq!(u8.apply(0b1100, 0b1010, Operator::Or), Ok((0b1110, false)));
+        assert_eq!(u8.apply(0b1100, 0b1010, Operator::Xor), Ok((0b0110, false)));
+        assert_eq!(u8.not(0b1111), 0b1111_0000);
+        assert_eq!(u8.apply(0x81, 1, Operator::ShiftLeft), Ok((0x02, true)));
+        assert_eq!(u8.apply(0x81, 1, Operator::ShiftRight), Ok((0x40, false)));
+        assert_eq!(u8.apply(0x81, 1, Operator::RotateLeft), Ok((0x03, false)));
+        assert_eq!(u8.apply(0x81, 1, Operator::RotateRight), Ok((0xC0, false)));
+        assert_eq!(u8.apply(1, 9, Operator::RotateLeft), Ok((2, false)));
+
+        let i8 = word(8, true);
+        assert_eq!(i8.not(0), -1);
+        assert_eq!(i8.apply(-128, 1, Operator::ShiftRight), Ok((-64, false)));
+        assert_eq!(i8.apply(-1, 1, Operator::ShiftLeft), Ok((-2, false)));
+        assert_eq!(i8.apply(64, 1, Operator::ShiftLeft), Ok((-128, true)));
+        assert!(i8.apply(1, -1, Operator::ShiftLeft).is_err());
+    }
+
+    #[test]
+    fn formats_bit_patterns_per_radix() {
+        let i8 = word(8, true);
+        assert_eq!(i8.format(-1, Radix::Hex), "FF");
+        assert_eq!(i8.format(-1, Radix::Dec), "-1");
+        assert_eq!(i8.format(-1, Radix::Oct), "377");
+        assert_eq!(i8.format(5, Radix::Bin), "101");
+
+        let hex = IntegerSettings {
+            radix: Radix::Hex,
+            ..i8
+        };
+        assert_eq!(hex.literal(-31), "-0x1F");
+        assert_eq!(hex.digits(255), "FF");
+        assert_eq!(Radix::strip_prefix("0b101"), Some((Radix::Bin, "101")));
+        assert_eq!(Radix::strip_prefix("101"), None);
+    }
+}
@@ -0,0 +1,35 @@
+//! Calculator engine behind `calculator_cli`.
+//!
+//! The engine has no dependency on a terminal: the interactive ratatui front
+//! end lives in the binary behind the `tui` feature, so other tools can link
+//! the evaluator with `default-features = false`.
+
+mod currency;
+mod engine;
+mod error;
+mod format;
+mod history;
+mod integer;
+mod settings;
+#[cfg(test)]
+mod temp_dir;
+mod token;
+mod unit;
+mod value;
+
+pub use currency::{Rates, RatesStore};
+pub use engine::{Engine, evaluate_expression};
+pub use error::{Error, ErrorKind};
+pub use format::{
+    AUTO_SCIENTIFIC_BELOW, AUTO_SCIENTIFIC_FROM, MAX_FIXED_DECIMALS, Notation, NumberFormat,
+};
+pub use history::{DEFAULT_HISTORY_LIMIT, History, HistoryEntry, HistoryStore};
+pub use integer::{IntegerSettings, Radix, WORD_SIZES};
+pub use settings::{Settings, SettingsStore, parse_angle_unit, parse_notation};
+pub use token::{Function, Operator, Token, tokenize};
+pub use unit::{Quantity, Unit};
+pub use value::{
+    AngleUnit, Backend, DEFAULT_DECIMAL_PRECISION, DecimalSettings, FloatMode, FractionStyle,
+    MAX_DECIMAL_PRECISION, ROUNDING_MODES, RoundingMode, Value, parse_rounding_mode,
+    rounding_mode_name,
+};
@@ -0,0 +1,185 @@
+/// A single line of text with a movable cursor, for typing a whole
+/// expression instead of building it key by key.
+///
+/// The cursor is a character index, from `0` (before the first character)
+/// to the number of characters (after the last).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LineEditor {
+    text: String,
+    cursor: usize,
+}
+
+impl LineEditor {
+    /// An editor holding `text` with the cursor at its end.
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let cursor = text.chars().count();
+        Self { text, cursor }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Moves the cursor to character index `cursor`, or the end if that is
+    /// past it.
+    pub fn set_cursor(&mut self, cursor: usize) {
+        self.cursor = cursor.min(self.len());
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    /// Byte offset of character index `index`.
+    fn byte_index(&self, index: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(index)
+            .map_or(self.text.len(), |(byte, _)| byte)
+    }
+
+    fn chars_before_cursor(&self) -> impl Iterator<Item = char> + '_ {
+        self.text[..self.byte_index(self.cursor)].chars().rev()
+    }
+
+    fn chars_after_cursor(&self) -> impl Iterator<Item = char> + '_ {
+        self.text[self.byte_index(self.cursor)..].chars()
+    }
+
+    /// Inserts `ch` before the cursor.
+    pub fn insert(&mut self, ch: char) {
+        let byte = self.byte_index(self.cursor);
+        self.text.insert(byte, ch);
+        self.cursor += 1;
+    }
+
+    /// Removes the characters between character indices `start` and `end`
+    /// and leaves the cursor at `start`.
+    fn remove(&mut self, start: usize, end: usize) {
+        let range = self.byte_index(start)..self.byte_index(end);
+        self.text.replace_range(range, "");
+        self.cursor = start;
+    }
+
+    /// Deletes the character before the cursor.
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.remove(self.cursor - 1, self.cursor);
+        }
+    }
+
+    /// Deletes the character under the cursor.
+    pub fn delete(&mut self) {
+        if self.cursor < self.len() {
+            self.remove(self.cursor, self.cursor + 1);
+        }
+    }
+
+    /// Deletes the word before the cursor and the spaces after it, like
+    /// Ctrl+W in a shell.
+    pub fn delete_word(&mut self) {
+        let start = self.word_start();
+        self.remove(start, self.cursor);
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.len());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.len();
+    }
+
+    /// Moves to the start of the word before the cursor.
+    pub fn word_left(&mut self) {
+        self.cursor = self.word_start();
+    }
+
+    /// Moves past the end of the word after the cursor.
+    pub fn word_right(&mut self) {
+        let spaces = self
+            .chars_after_cursor()
+            .take_while(|ch| ch.is_whitespace())
+            .count();
+        let word = self
+            .chars_after_cursor()
+            .skip(spaces)
+            .take_while(|ch| !ch.is_whitespace())
+            .count();
+        self.cursor += spaces + word;
+    }
+
+    fn word_start(&self) -> usize {
+        let spaces = self
+            .chars_before_cursor()
+            .take_while(|ch| ch.is_whitespace())
+            .count();
+        let word = self
+            .chars_before_cursor()
+            .skip(spaces)
+            .take_while(|ch| !ch.is_whitespace())
+            .count();
+        self.cursor - spaces - word
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserts_and_deletes_around_the_cursor() {
+        let mut editor = LineEditor::new("2 + 4");
+        editor.move_left();
+        editor.insert('1');
+        assert_eq!(editor.text(), "2 + 14");
+
+        editor.home();
+        editor.delete();
+        editor.insert('3');
+        assert_eq!(editor.text(), "3 + 14");
+        assert_eq!(editor.cursor(), 1);
+
+        editor.backspace();
+        editor.backspace();
+        editor.move_left();
+        assert_eq!(editor.text(), " + 14");
+        assert_eq!(editor.cursor(), 0);
+
+        editor.end();
+        editor.delete();
+        assert_eq!(editor.cursor(), 5);
+    }
+
+    #[test]
+    fn moves_and_deletes_by_word() {
+        let mut editor = LineEditor::new("12 × sqrt(16)  ");
+        editor.word_left();
+        assert_eq!(editor.cursor(), 5);
+        editor.word_left();
+        editor.word_right();
+        assert_eq!(editor.cursor(), 4);
+
+        editor.end();
+        editor.delete_word();
+        assert_eq!(editor.text(), "12 × ");
+        editor.delete_word();
+        editor.delete_word();
+        assert_eq!(editor.text(), "");
+        editor.delete_word();
+        assert_eq!(editor.cursor(), 0);
+    }
+}
@@ -1,502 +1,400 @@
-use std::io;
-
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
-use ratatui::{
-    DefaultTerminal, Frame,
-    buffer::Buffer,
-    layout::{Constraint, Layout},
-    style::{Modifier, Style},
-    text::{Line, Span},
-    widgets::{Block, Paragraph, Widget},
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
-fn main() -> io::Result<()> {
-    let mut terminal = ratatui::init();
-    let app_result = App::default().run(&mut terminal);
-    ratatui::restore();
-    app_result
-}
+use app::{App, error_text};
+use calculator_cli::{
+    Backend, DEFAULT_HISTORY_LIMIT, Engine, FloatMode, History, HistoryStore,
+    MAX_DECIMAL_PRECISION, MAX_FIXED_DECIMALS, RatesStore, SettingsStore, parse_angle_unit,
+    parse_notation, parse_rounding_mode,
+};
 
-/// Stateful calculator application.
-///
-/// Inspired by the “deep module” principle from Ousterhout’s *A Philosophy of
-/// Software Design*, `App` keeps the entire calculator state (current input,
-/// committed tokens, error handling, and event-driven behavior) behind a single
-/// interface so the rest of the program interacts with a clear abstraction
-/// boundary.
-#[derive(Debug, Default, Clone)]
-pub struct App {
-    input: String,
-    tokens: Vec<Token>,
-    just_evaluated: bool,
-    error_message: Option<String>,
-    exit: bool,
-}
+mod app;
+mod line_editor;
 
-#[derive(Debug, Clone)]
-enum Token {
-    Number(String),
-    Operator(Operator),
-}
+const USAGE: &str = "usage: calculator_cli [--decimal | --rational | --integer] [--precision DIGITS] \
+                     [--rounding MODE] [--angle rad|deg|grad] [--ieee] \
+                     [--notation plain|auto|sci|eng] [--decimals PLACES] [--grouping] [-e EXPRESSION | --file PATH]";
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Operator {
-    Add,
-    Subtract,
-    Multiply,
-    Divide,
-}
+/// Environment variable overriding how many history entries are kept.
+const HISTORY_LIMIT_VAR: &str = "CALCULATOR_CLI_HISTORY_LIMIT";
 
-impl Operator {
-    fn symbol(self) -> char {
-        match self {
-            Operator::Add => '+',
-            Operator::Subtract => '-',
-            Operator::Multiply => '×',
-            Operator::Divide => '÷',
-        }
-    }
+/// What to do after the options are parsed.
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Interactive,
+    Eval(String),
+    File(PathBuf),
 }
 
-impl App {
-    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
-        while !self.exit {
-            terminal.draw(|frame| self.draw(frame))?;
-            self.handle_events()?;
-        }
-        Ok(())
-    }
-
-    fn draw(&self, frame: &mut Frame) {
-        frame.render_widget(self, frame.area());
-    }
-
-    fn handle_events(&mut self) -> io::Result<()> {
-        match event::read()? {
-            Event::Key(key) if key.kind == KeyEventKind::Press => self.handle_key_events(key),
-            _ => {}
-        }
-
-        Ok(())
-    }
-
-    fn handle_key_events(&mut self, key: KeyEvent) {
-        if self.error_message.is_some() {
-            match key.code {
-                KeyCode::Char('a') | KeyCode::Char('A') => self.all_clear(),
-                KeyCode::Char('q') => self.exit = true,
-                _ => {}
-            }
-            return;
-        }
-
-        match key.code {
-            KeyCode::Char('q') => self.exit = true,
-            KeyCode::Char('a') | KeyCode::Char('A') => self.all_clear(),
-            KeyCode::Enter | KeyCode::Char('=') => self.evaluate(),
-            KeyCode::Char('+') => self.set_operator(Operator::Add),
-            KeyCode::Char('-') => self.set_operator(Operator::Subtract),
-            KeyCode::Char('*') | KeyCode::Char('x') | KeyCode::Char('X') => {
-                self.set_operator(Operator::Multiply)
-            }
-            KeyCode::Char('/') | KeyCode::Char(':') => self.set_operator(Operator::Divide),
-            KeyCode::Char('.') => self.handle_decimal_point(),
-            KeyCode::Backspace => self.handle_backspace(),
-            KeyCode::Char(ch) if ch.is_ascii_digit() => self.handle_digit(ch),
-            _ => {}
-        }
+fn main() -> io::Result<ExitCode> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mode = match parse_args(args.clone(), Engine::new()) {
+        Ok((mode, _)) => mode,
+        Err(message) => {
+            eprintln!("{message}\n{USAGE}");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    // scripts get the same results whatever was last used interactively
+    let interactive = mode == Mode::Interactive && io::stdin().is_terminal();
+    let settings_store = interactive
+        .then(SettingsStore::default_path)
+        .flatten()
+        .map(SettingsStore::new);
+    let mut engine = Engine::new();
+    if let Some(store) = &settings_store {
+        load_settings(store, &mut engine);
     }
-
-    fn all_clear(&mut self) {
-        self.input.clear();
-        self.tokens.clear();
-        self.error_message = None;
-        self.just_evaluated = false;
+    if let Some(store) = RatesStore::default_path().map(RatesStore::new) {
+        load_rates(&store, &mut engine);
     }
-
-    fn handle_digit(&mut self, digit: char) {
-        if self.just_evaluated {
-            self.input.clear();
-            self.just_evaluated = false;
-        }
-
-        if self.input == "0" {
-            self.input.clear();
-        }
-
-        self.input.push(digit);
+    // the options were checked above
+    let Ok((mode, engine)) = parse_args(args, engine) else {
+        return Ok(ExitCode::FAILURE);
+    };
+
+    match mode {
+        Mode::Eval(expression) => return Ok(evaluate_once(engine, &expression)),
+        Mode::File(path) => return Ok(evaluate_file(engine, &path)),
+        Mode::Interactive if !io::stdin().is_terminal() => {
+            return evaluate_batch(engine, io::stdin().lock());
+        }
+        Mode::Interactive => {}
     }
 
-    fn handle_decimal_point(&mut self) {
-        if self.just_evaluated {
-            self.input.clear();
-            self.just_evaluated = false;
-        }
+    let store = history_store();
+    let history = store.as_ref().map_or_else(History::new, load_history);
 
-        if self.input.is_empty() {
-            self.input.push('0');
-        }
-        if !self.input.contains('.') {
-            self.input.push('.');
-        }
-    }
+    let mut app = App::new(engine, history);
+    let mut terminal = ratatui::init();
+    let app_result = app.run(&mut terminal);
+    ratatui::restore();
+    app_result?;
 
-    fn handle_backspace(&mut self) {
-        if self.just_evaluated || self.input.is_empty() {
-            return;
-        }
-        self.input.pop();
+    if let Some(store) = store
+        && let Err(err) = store.save(app.history())
+    {
+        eprintln!("{}: could not save history: {err}", store.path().display());
     }
-
-    fn set_operator(&mut self, operator: Operator) {
-        if !self.try_commit_input() {
-            return;
-        }
-
-        if self.tokens.is_empty() {
-            // no operand to attach the operator to
-            return;
-        }
-
-        match self.tokens.last_mut() {
-            Some(Token::Operator(current)) => *current = operator,
-            _ => self.tokens.push(Token::Operator(operator)),
-        }
-        self.just_evaluated = false;
+    if let Some(store) = settings_store
+        && let Err(err) = store.save(&app.engine().settings())
+    {
+        eprintln!("{}: could not save settings: {err}", store.path().display());
     }
+    Ok(ExitCode::SUCCESS)
+}
 
-    fn evaluate(&mut self) {
-        if !self.try_commit_input() {
-            return;
-        }
-        if let Some(Token::Operator(_)) = self.tokens.last() {
-            // trailing operator means expression is incomplete
-            return;
-        }
-        if self.tokens.is_empty() {
-            return;
-        }
-
-        match self.evaluate_tokens() {
-            Ok(result) => {
-                self.input = self.format_number(result);
-                self.tokens.clear();
-                self.just_evaluated = true;
+/// Parses the command line into a mode and `engine` with the number options
+/// applied on top of its settings.
+fn parse_args(
+    args: impl IntoIterator<Item = String>,
+    mut engine: Engine,
+) -> Result<(Mode, Engine), String> {
+    let mut args = args.into_iter();
+    let mut mode = Mode::Interactive;
+    let mut decimal = engine.decimal_settings();
+    let mut format = engine.number_format();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+        match arg.as_str() {
+            "-e" | "--eval" => mode = Mode::Eval(value()?),
+            "-f" | "--file" => mode = Mode::File(value()?.into()),
+            "--decimal" => engine.set_backend(Backend::Decimal),
+            "--rational" => engine.set_backend(Backend::Rational),
+            "--integer" => engine.set_backend(Backend::Integer),
+            "--ieee" => engine.set_float_mode(FloatMode::Ieee),
+            "--precision" => {
+                decimal.precision = value()?
+                    .parse()
+                    .ok()
+                    .filter(|digits| (1..=MAX_DECIMAL_PRECISION).contains(digits))
+                    .ok_or(format!(
+                        "--precision must be between 1 and {MAX_DECIMAL_PRECISION}"
+                    ))?;
             }
-            Err(msg) => self.set_error(msg),
-        }
-    }
-
-    fn evaluate_tokens(&self) -> Result<f64, &'static str> {
-        let mut values = Vec::new();
-        let mut operators = Vec::new();
-        let mut expect_number = true;
-
-        for token in &self.tokens {
-            match token {
-                Token::Number(text) => {
-                    if !expect_number {
-                        return Err("invalid expression");
-                    }
-                    let value = text
-                        .parse::<f64>()
-                        .map_err(|_| "invalid number in expression")?;
-                    values.push(value);
-                    expect_number = false;
-                }
-                Token::Operator(op) => {
-                    if expect_number {
-                        return Err("incomplete expression");
-                    }
-                    operators.push(*op);
-                    expect_number = true;
-                }
+            "--rounding" => {
+                let name = value()?;
+                decimal.rounding =
+                    parse_rounding_mode(&name).ok_or(format!("unknown rounding mode `{name}`"))?;
             }
-        }
-
-        if values.is_empty() {
-            return Err("incomplete expression");
-        }
-
-        let mut values = values;
-        let mut operators = operators;
-
-        let mut idx = 0;
-        while idx < operators.len() {
-            match operators[idx] {
-                Operator::Multiply | Operator::Divide => {
-                    let lhs = values[idx];
-                    let rhs = values[idx + 1];
-                    let result = self.apply_operator(lhs, rhs, operators[idx])?;
-                    values[idx] = result;
-                    values.remove(idx + 1);
-                    operators.remove(idx);
-                }
-                _ => idx += 1,
+            "--angle" => {
+                let name = value()?;
+                let unit = parse_angle_unit(&name).ok_or(format!("unknown angle unit `{name}`"))?;
+                engine.set_angle_unit(unit);
             }
+            "--notation" => {
+                let name = value()?;
+                format.notation =
+                    parse_notation(&name).ok_or(format!("unknown notation `{name}`"))?;
+            }
+            "--decimals" => {
+                format.decimals = Some(
+                    value()?
+                        .parse()
+                        .ok()
+                        .filter(|places| *places <= MAX_FIXED_DECIMALS)
+                        .ok_or(format!(
+                            "--decimals must be between 0 and {MAX_FIXED_DECIMALS}"
+                        ))?,
+                );
+            }
+            "--grouping" => format.grouping = true,
+            _ => return Err(format!("unexpected argument `{arg}`")),
         }
-
-        let mut result = values[0];
-        for (op, rhs) in operators.into_iter().zip(values.into_iter().skip(1)) {
-            result = self.apply_operator(result, rhs, op)?;
-        }
-        Ok(result)
     }
 
-    fn try_commit_input(&mut self) -> bool {
-        if self.input.is_empty() {
-            return true;
-        }
+    engine.set_decimal_settings(decimal);
+    engine.set_number_format(format);
+    Ok((mode, engine))
+}
 
-        match self.input.parse::<f64>() {
-            Ok(_) => {
-                self.tokens.push(Token::Number(self.input.clone()));
-                self.input.clear();
-                self.just_evaluated = false;
-                true
-            }
-            Err(_) => {
-                self.set_error("invalid number");
-                false
+/// Applies the saved settings to `engine`, reporting problems on stderr. An
+/// unreadable file keeps the defaults.
+fn load_settings(store: &SettingsStore, engine: &mut Engine) {
+    match store.load() {
+        Ok((settings, warnings)) => {
+            for warning in warnings {
+                eprintln!("warning: {warning}");
             }
+            engine.apply_settings(settings);
         }
+        Err(err) => eprintln!(
+            "warning: {}: could not read settings: {err}",
+            store.path().display()
+        ),
     }
+}
 
-    fn apply_operator(&self, lhs: f64, rhs: f64, operator: Operator) -> Result<f64, &'static str> {
-        match operator {
-            Operator::Add => Ok(lhs + rhs),
-            Operator::Subtract => Ok(lhs - rhs),
-            Operator::Multiply => Ok(lhs * rhs),
-            Operator::Divide => {
-                if rhs.abs() < f64::EPSILON {
-                    Err("Cannot divide by zero")
-                } else {
-                    Ok(lhs / rhs)
-                }
+/// Gives `engine` the currencies in the rates file, reporting problems on
+/// stderr. Without a readable file there are no currency units.
+fn load_rates(store: &RatesStore, engine: &mut Engine) {
+    match store.load() {
+        Ok((rates, warnings)) => {
+            for warning in warnings {
+                eprintln!("warning: {warning}");
             }
+            engine.set_rates(rates);
         }
+        Err(err) => eprintln!(
+            "warning: {}: could not read rates: {err}",
+            store.path().display()
+        ),
     }
+}
 
-    fn set_error(&mut self, message: &'static str) {
-        self.error_message = Some(format!("Error {}", message));
-        self.input.clear();
-        self.tokens.clear();
-        self.just_evaluated = false;
-    }
+fn history_store() -> Option<HistoryStore> {
+    let limit = env::var(HISTORY_LIMIT_VAR)
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_LIMIT);
+    HistoryStore::default_path().map(|path| HistoryStore::new(path, limit))
+}
 
-    fn format_number(&self, value: f64) -> String {
-        let mut output = format!("{}", value);
-        if output.contains('.') {
-            while output.ends_with('0') {
-                output.pop();
-            }
-            if output.ends_with('.') {
-                output.pop();
+/// Loads the saved history, reporting problems on stderr before the terminal
+/// is taken over. An unreadable file starts an empty history.
+fn load_history(store: &HistoryStore) -> History {
+    match store.load() {
+        Ok((history, warnings)) => {
+            for warning in warnings {
+                eprintln!("warning: {warning}");
             }
+            history
         }
-        if output.is_empty() {
-            "0".into()
-        } else {
-            output
+        Err(err) => {
+            eprintln!(
+                "warning: {}: could not read history: {err}",
+                store.path().display()
+            );
+            History::new()
         }
     }
+}
 
-    fn display_value(&self) -> String {
-        if let Some(err) = &self.error_message {
-            return err.clone();
-        }
-        if !self.input.is_empty() {
-            return self.input.clone();
+/// Evaluates `expression` for scripts: the result goes to stdout, an error
+/// goes to stderr together with a failing exit status.
+fn evaluate_once(mut engine: Engine, expression: &str) -> ExitCode {
+    match engine.evaluate_line(expression).map_err(error_text) {
+        Ok(result) => {
+            println!("{result}");
+            ExitCode::SUCCESS
         }
-        if let Some(value) = self.tokens.iter().rev().find_map(|token| match token {
-            Token::Number(number) => Some(number.clone()),
-            Token::Operator(_) => None,
-        }) {
-            return value;
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
         }
-        "0".into()
     }
+}
 
-    fn expression_line(&self) -> String {
-        if let Some(err) = &self.error_message {
-            return format!("{err} (press A to clear)");
-        }
-
-        let mut parts: Vec<String> = self
-            .tokens
-            .iter()
-            .map(|token| match token {
-                Token::Number(number) => number.clone(),
-                Token::Operator(op) => op.symbol().to_string(),
-            })
-            .collect();
-        if !self.input.is_empty() {
-            parts.push(self.input.clone());
-        }
+/// Evaluates the lines of the file at `path` like [`evaluate_batch`]. A
+/// file that cannot be read is reported on stderr with a failing exit
+/// status.
+fn evaluate_file(engine: Engine, path: &Path) -> ExitCode {
+    let result = File::open(path).and_then(|file| evaluate_batch(engine, BufReader::new(file)));
+    result.unwrap_or_else(|err| {
+        eprintln!("{}: {err}", path.display());
+        ExitCode::FAILURE
+    })
+}
 
-        if parts.is_empty() {
-            "Enter digits and choose an operator".into()
-        } else {
-            parts.join(" ")
-        }
-    }
+fn evaluate_batch(mut engine: Engine, reader: impl BufRead) -> io::Result<ExitCode> {
+    let all_ok = evaluate_lines(
+        &mut engine,
+        reader,
+        &mut io::stdout().lock(),
+        &mut io::stderr().lock(),
+    )?;
+    Ok(if all_ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
 }
 
-impl Widget for &App {
-    fn render(self, area: ratatui::prelude::Rect, buf: &mut Buffer) {
-        let layout = Layout::vertical([
-            Constraint::Length(3),
-            Constraint::Length(3),
-            Constraint::Length(3),
-        ])
-        .split(area);
-
-        let expression = Paragraph::new(self.expression_line())
-            .block(Block::bordered().title("Expression"))
-            .alignment(ratatui::layout::Alignment::Right);
-
-        let value = Paragraph::new(Span::styled(
-            self.display_value(),
-            Style::default().add_modifier(Modifier::BOLD),
-        ))
-        .alignment(ratatui::layout::Alignment::Right)
-        .block(Block::bordered().title("Result"));
-
-        let instruction = Paragraph::new(Line::from(vec![
-            Span::styled("Digits 0-9", Style::default().add_modifier(Modifier::BOLD)),
-            "· + - * : ".into(),
-            "· Enter/=: evaluate ".into(),
-            "· A: AC ".into(),
-            "· Q: Quit".into(),
-        ]))
-        .block(Block::bordered());
-
-        expression.render(layout[0], buf);
-        value.render(layout[1], buf);
-        instruction.render(layout[2], buf);
+/// Evaluates one expression per line, writing each result to `out`.
+///
+/// Lines share `engine`, so `name = expression` lines define variables for
+/// the lines after them. Blank lines are skipped. A failing line is reported
+/// to `err` with its 1-based line number and does not stop the run; the
+/// return value tells whether every line succeeded.
+fn evaluate_lines(
+    engine: &mut Engine,
+    reader: impl BufRead,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> io::Result<bool> {
+    let mut all_ok = true;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match engine.evaluate_line(&line).map_err(error_text) {
+            Ok(result) => writeln!(out, "{result}")?,
+            Err(message) => {
+                all_ok = false;
+                writeln!(err, "line {}: {message}", index + 1)?;
+            }
+        }
     }
+    Ok(all_ok)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ratatui::{buffer::Buffer, layout::Rect};
+    use calculator_cli::AngleUnit;
 
     #[test]
-    fn digit_entry_and_decimal_behavior() {
-        let mut app = App::default();
-        app.handle_digit('0');
-        app.handle_digit('5');
-        assert_eq!(app.input, "5");
-
-        app.handle_decimal_point();
-        app.handle_digit('2');
-        assert_eq!(app.input, "5.2");
-
-        app.set_operator(Operator::Add);
-        app.handle_digit('1');
-        app.evaluate();
-        assert_eq!(app.display_value(), "6.2");
-        assert!(app.just_evaluated);
-
-        app.handle_digit('3');
-        assert_eq!(app.input, "3");
+    fn evaluate_lines_reports_errors_and_continues() {
+        let input = "1 + 1\n\n8 / 0\n(2 + 3) × 4\n2 +\n";
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+
+        let all_ok =
+            evaluate_lines(&mut Engine::new(), input.as_bytes(), &mut out, &mut err).unwrap();
+
+        assert!(!all_ok);
+        assert_eq!(String::from_utf8(out).unwrap(), "2\n20\n");
+        let err = String::from_utf8(err).unwrap();
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("line 3: ") && lines[0].contains("Cannot divide"));
+        assert!(lines[1].starts_with("line 5: "));
     }
 
     #[test]
-    fn backspace_removes_last_digit() {
-        let mut app = App::default();
-        app.handle_digit('2');
-        app.handle_digit('0');
-        app.handle_digit('0');
-        app.handle_digit('0');
-
-        app.handle_backspace();
-        app.handle_backspace();
-        assert_eq!(app.input, "20");
-
-        app.set_operator(Operator::Add);
-        app.handle_digit('1');
-        app.evaluate();
-        assert_eq!(app.display_value(), "21");
+    fn evaluate_lines_share_variables() {
+        let input = "price = 20\nqty = 3\nprice × qty\n";
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+
+        assert!(evaluate_lines(&mut Engine::new(), input.as_bytes(), &mut out, &mut err).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), "20\n3\n60\n");
     }
 
     #[test]
-    fn full_expression_respects_precedence() {
-        let mut app = App::default();
-        for ch in "10".chars() {
-            app.handle_digit(ch);
-        }
-        app.set_operator(Operator::Add);
-
-        for ch in "10".chars() {
-            app.handle_digit(ch);
-        }
-        app.set_operator(Operator::Multiply);
-        app.handle_digit('5');
-
-        app.set_operator(Operator::Divide);
-        app.handle_digit('4');
-
-        app.set_operator(Operator::Add);
-        for ch in "45".chars() {
-            app.handle_digit(ch);
-        }
+    fn evaluate_file_fails_on_a_missing_file() {
+        let path = env::temp_dir().join(format!("calculator_cli-{}-missing", std::process::id()));
+        assert_eq!(evaluate_file(Engine::new(), &path), ExitCode::FAILURE);
+    }
 
-        app.evaluate();
-        assert_eq!(app.display_value(), "67.5");
-        assert!(app.tokens.is_empty());
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
     }
 
     #[test]
-    fn divide_by_zero_sets_error() {
-        let mut app = App::default();
-        app.handle_digit('8');
-        app.set_operator(Operator::Divide);
-        app.handle_digit('0');
-        app.evaluate();
-
-        assert!(
-            app.error_message
-                .as_deref()
-                .is_some_and(|msg| msg.contains("Cannot divide"))
-        );
+    fn parse_args_configures_decimal_backend() {
+        let (mode, mut engine) = parse_args(
+            args(&[
+                "--decimal",
+                "--precision",
+                "4",
+                "--rounding",
+                "half-up",
+                "-e",
+                "2 / 3",
+            ]),
+            Engine::new(),
+        )
+        .unwrap();
+
+        assert_eq!(mode, Mode::Eval("2 / 3".into()));
+        assert_eq!(engine.backend(), Backend::Decimal);
+        assert_eq!(engine.evaluate_line("2 / 3"), Ok("0.6667".into()));
+        assert_eq!(engine.evaluate_line("0.1 + 0.2"), Ok("0.3".into()));
     }
 
     #[test]
-    fn all_clear_resets_state() {
-        let mut app = App::default();
-        app.handle_digit('9');
-        app.set_operator(Operator::Subtract);
-        app.handle_digit('4');
-        app.evaluate();
-        assert!(app.just_evaluated);
-
-        app.all_clear();
-        assert!(app.input.is_empty());
-        assert!(app.tokens.is_empty());
-        assert!(app.error_message.is_none());
-        assert!(!app.just_evaluated);
+    fn parse_args_overrides_saved_settings() {
+        let mut saved = Engine::new();
+        saved.set_backend(Backend::Rational);
+        saved.set_angle_unit(AngleUnit::Degrees);
+
+        let (_, engine) = parse_args(args(&["--angle", "grad"]), saved).unwrap();
+
+        assert_eq!(engine.backend(), Backend::Rational);
+        assert_eq!(engine.angle_unit(), AngleUnit::Gradians);
+        assert!(parse_args(args(&["--angle", "turns"]), Engine::new()).is_err());
     }
 
     #[test]
-    fn render_shows_expression_result_and_instructions() {
-        let app = App::default();
-        let area = Rect::new(0, 0, 60, 9);
-        let mut buf = Buffer::empty(area);
+    fn parse_args_sets_the_number_format() {
+        let (_, mut engine) = parse_args(
+            args(&["--notation", "eng", "--decimals", "2", "--grouping"]),
+            Engine::new(),
+        )
+        .unwrap();
+
+        assert_eq!(engine.evaluate_line("1234 × 10"), Ok("12.34e3".into()));
+        assert!(parse_args(args(&["--decimals", "16"]), Engine::new()).is_err());
+        assert!(parse_args(args(&["--notation", "fancy"]), Engine::new()).is_err());
+    }
 
-        (&app).render(area, &mut buf);
+    #[test]
+    fn parse_args_enables_ieee_floats() {
+        let (_, mut engine) = parse_args(args(&["--ieee"]), Engine::new()).unwrap();
 
-        assert!(row_string(&buf, 1, area.width).contains("Enter digits"));
-        assert!(row_string(&buf, 4, area.width).contains("0"));
-        assert!(row_string(&buf, 7, area.width).contains("Digits 0-9"));
+        assert_eq!(engine.float_mode(), FloatMode::Ieee);
+        assert_eq!(engine.evaluate_line("-1 / 0"), Ok("-∞".into()));
     }
 
-    fn row_string(buf: &Buffer, row: u16, width: u16) -> String {
-        let mut line = String::new();
-        for x in 0..width {
-            line.push_str(buf[(x, row)].symbol());
-        }
-        line
+    #[test]
+    fn parse_args_rejects_bad_options() {
+        assert!(parse_args(args(&["--precision", "0"]), Engine::new()).is_err());
+        assert!(parse_args(args(&["--rounding", "sideways"]), Engine::new()).is_err());
+        assert!(parse_args(args(&["-e"]), Engine::new()).is_err());
+        assert!(parse_args(args(&["--bogus"]), Engine::new()).is_err());
+        assert_eq!(
+            parse_args(args(&[]), Engine::new()).unwrap().0,
+            Mode::Interactive
+        );
     }
 }
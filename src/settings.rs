@@ -0,0 +1,303 @@
+//! Calculator settings and their on-disk store.
+//!
+//! The settings file is line oriented, one `key = value` pair per line:
+//!
+//! ```text
+//! backend = decimal
+//! precision = 34
+//! rounding = half-even
+//! fractions = mixed
+//! notation = auto
+//! grouping = true
+//! decimals = 2
+//! angle = deg
+//! floats = ieee
+//! word = 32
+//! signed = true
+//! radix = hex
+//! ```
+//!
+//! Values use the same names as the interface. Missing keys keep their
+//! defaults; unknown keys and values are skipped with a warning when the
+//! file is loaded.
+
+use std::{
+    env, fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    format::{MAX_FIXED_DECIMALS, Notation, NumberFormat},
+    integer::{IntegerSettings, Radix, WORD_SIZES},
+    value::{
+        AngleUnit, Backend, DecimalSettings, FloatMode, FractionStyle, MAX_DECIMAL_PRECISION,
+        parse_rounding_mode, rounding_mode_name,
+    },
+};
+
+/// Everything about how numbers are computed and shown that outlives a
+/// session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub backend: Backend,
+    pub decimal: DecimalSettings,
+    pub fraction_style: FractionStyle,
+    pub number_format: NumberFormat,
+    pub angle_unit: AngleUnit,
+    pub float_mode: FloatMode,
+    pub integer: IntegerSettings,
+}
+
+/// Reads and writes [`Settings`] in the file format described in the module
+/// documentation.
+#[derive(Debug, Clone)]
+pub struct SettingsStore {
+    path: PathBuf,
+}
+
+impl SettingsStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `$XDG_CONFIG_HOME/calculator_cli/settings`, falling back to
+    /// `~/.config` when `XDG_CONFIG_HOME` is unset or not absolute.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("settings"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the settings. A missing file gives the defaults.
+    pub fn load(&self) -> io::Result<(Settings, Vec<String>)> {
+        let mut settings = Settings::default();
+        let mut warnings = Vec::new();
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok((settings, warnings)),
+            Err(err) => return Err(err),
+        };
+
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if !apply_line(&mut settings, line) {
+                warnings.push(format!(
+                    "{}: skipping unknown setting on line {}",
+                    self.path.display(),
+                    index + 1
+                ));
+            }
+        }
+        Ok((settings, warnings))
+    }
+
+    pub fn save(&self, settings: &Settings) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let contents = format!(
+            "backend = {}\nprecision = {}\nrounding = {}\nfractions = {}\nnotation = {}\n\
+             grouping = {}\ndecimals = {}\nangle = {}\nfloats = {}\nword = {}\nsigned = {}\n\
+             radix = {}\n",
+            settings.backend.name(),
+            settings.decimal.precision,
+            rounding_mode_name(settings.decimal.rounding),
+            settings.fraction_style.name(),
+            settings.number_format.notation.name(),
+            settings.number_format.grouping,
+            settings
+                .number_format
+                .decimals
+                .map_or("all".into(), |places| places.to_string()),
+            settings.angle_unit.name(),
+            settings.float_mode.name(),
+            settings.integer.bits,
+            settings.integer.signed,
+            settings.integer.radix.name(),
+        );
+        fs::write(&self.path, contents)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/calculator_cli`, falling back to `~/.config` when
+/// `XDG_CONFIG_HOME` is unset or not absolute.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("calculator_cli"))
+}
+
+/// Applies one `key = value` line, returning whether it was understood.
+fn apply_line(settings: &mut Settings, line: &str) -> bool {
+    let Some((key, value)) = line.split_once('=') else {
+        return false;
+    };
+    let value = value.trim();
+
+    match key.trim() {
+        "backend" => {
+            let backends = [
+                Backend::Float,
+                Backend::Decimal,
+                Backend::Rational,
+                Backend::Integer,
+            ];
+            backends
+                .into_iter()
+                .find(|backend| backend.name() == value)
+                .map(|backend| settings.backend = backend)
+                .is_some()
+        }
+        "precision" => value
+            .parse()
+            .ok()
+            .filter(|digits| (1..=MAX_DECIMAL_PRECISION).contains(digits))
+            .map(|digits| settings.decimal.precision = digits)
+            .is_some(),
+        "rounding" => parse_rounding_mode(value)
+            .map(|mode| settings.decimal.rounding = mode)
+            .is_some(),
+        "fractions" => {
+            let styles = [
+                FractionStyle::Improper,
+                FractionStyle::Mixed,
+                FractionStyle::Decimal,
+            ];
+            styles
+                .into_iter()
+                .find(|style| style.name() == value)
+                .map(|style| settings.fraction_style = style)
+                .is_some()
+        }
+        "notation" => parse_notation(value)
+            .map(|notation| settings.number_format.notation = notation)
+            .is_some(),
+        "grouping" => value
+            .parse()
+            .map(|grouping| settings.number_format.grouping = grouping)
+            .is_ok(),
+        "decimals" if value == "all" => {
+            settings.number_format.decimals = None;
+            true
+        }
+        "decimals" => value
+            .parse()
+            .ok()
+            .filter(|places| *places <= MAX_FIXED_DECIMALS)
+            .map(|places| settings.number_format.decimals = Some(places))
+            .is_some(),
+        "angle" => parse_angle_unit(value)
+            .map(|unit| settings.angle_unit = unit)
+            .is_some(),
+        "floats" => [FloatMode::Checked, FloatMode::Ieee]
+            .into_iter()
+            .find(|mode| mode.name() == value)
+            .map(|mode| settings.float_mode = mode)
+            .is_some(),
+        "word" => value
+            .parse()
+            .ok()
+            .filter(|bits| WORD_SIZES.contains(bits))
+            .map(|bits| settings.integer.bits = bits)
+            .is_some(),
+        "signed" => value
+            .parse()
+            .map(|signed| settings.integer.signed = signed)
+            .is_ok(),
+        "radix" => Radix::ALL
+            .into_iter()
+            .find(|radix| radix.name() == value)
+            .map(|radix| settings.integer.radix = radix)
+            .is_some(),
+        _ => false,
+    }
+}
+
+/// The angle unit named `rad`, `deg` or `grad`.
+pub fn parse_angle_unit(name: &str) -> Option<AngleUnit> {
+    [AngleUnit::Radians, AngleUnit::Degrees, AngleUnit::Gradians]
+        .into_iter()
+        .find(|unit| unit.name() == name)
+}
+
+/// The notation named `plain`, `auto`, `sci` or `eng`.
+pub fn parse_notation(name: &str) -> Option<Notation> {
+    [
+        Notation::Plain,
+        Notation::Auto,
+        Notation::Scientific,
+        Notation::Engineering,
+    ]
+    .into_iter()
+    .find(|notation| notation.name() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{temp_dir::TempDir, value::RoundingMode};
+
+    fn temp_store(dir: &TempDir) -> SettingsStore {
+        SettingsStore::new(dir.path().join("settings"))
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let dir = TempDir::new("settings-round-trip");
+        let store = temp_store(&dir);
+        let settings = Settings {
+            backend: Backend::Rational,
+            decimal: DecimalSettings {
+                precision: 12,
+                rounding: RoundingMode::Floor,
+            },
+            fraction_style: FractionStyle::Mixed,
+            number_format: NumberFormat {
+                notation: Notation::Engineering,
+                grouping: true,
+                decimals: Some(3),
+            },
+            angle_unit: AngleUnit::Gradians,
+            float_mode: FloatMode::Ieee,
+            integer: IntegerSettings {
+                bits: 16,
+                signed: false,
+                radix: Radix::Bin,
+            },
+        };
+
+        store.save(&settings).unwrap();
+        let (loaded, warnings) = store.load().unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(loaded, settings);
+    }
+
+    #[test]
+    fn load_skips_unknown_lines_and_keeps_defaults() {
+        let dir = TempDir::new("settings-unknown");
+        let store = temp_store(&dir);
+        fs::write(
+            store.path(),
+            "angle = deg\ncolour = blue\nprecision = 0\n\nbackend=decimal\n",
+        )
+        .unwrap();
+
+        let (settings, warnings) = store.load().unwrap();
+
+        assert_eq!(settings.angle_unit, AngleUnit::Degrees);
+        assert_eq!(settings.backend, Backend::Decimal);
+        assert_eq!(settings.decimal, DecimalSettings::default());
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("line 2"));
+        assert!(warnings[1].contains("line 3"));
+    }
+}
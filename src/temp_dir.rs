@@ -0,0 +1,31 @@
+//! A scratch directory for tests that read and write files.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// An empty directory under the system temp dir, unique to this test run
+/// and `name`, removed again when dropped.
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("calculator_cli-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
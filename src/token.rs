@@ -0,0 +1,634 @@
+use std::{iter::Peekable, str::Chars};
+
+use crate::{currency::Rates, error::ErrorKind, integer::Radix, unit::Unit};
+
+/// A committed piece of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Number(String),
+    /// A named variable, resolved when the expression is evaluated.
+    Variable(String),
+    /// An earlier result, counted back from the last one: `Ans` is 0 and
+    /// `Ans1` the result before it. Resolved when the expression is
+    /// evaluated.
+    Answer(usize),
+    /// Unary minus in front of the next operand, as in `3 × -2`.
+    Negate,
+    /// A function applied to the next operand, as in `sqrt(2)` or `sin 30`.
+    Function(Function),
+    /// Makes the operand before it a percentage, as in `200 + 10%`.
+    Percent,
+    Operator(Operator),
+    LeftParen,
+    RightParen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// Remainder with the sign of the divisor, so that
+    /// `a = (a // b) × b + a % b`.
+    Modulo,
+    /// Division rounded down to an integer.
+    FloorDivide,
+    Power,
+    /// Bitwise operators, for the integer backend.
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    /// Rotates within the word size of the integer backend.
+    RotateLeft,
+    RotateRight,
+    /// Converts a quantity to the unit on the right: `5 km to mi`.
+    To,
+    /// The change from the left value to the right one, in percent:
+    /// `80 chg 100` is `25`.
+    PercentChange,
+    /// The price with a markup of the right value, in percent, on the cost
+    /// on the left: `80 markup 25` is `100`.
+    Markup,
+    /// The price that leaves a margin of the right value, in percent, of
+    /// the price: `75 margin 25` is `100`.
+    Margin,
+}
+
+impl Operator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "×",
+            Operator::Divide => "÷",
+            Operator::Modulo => "%",
+            Operator::FloorDivide => "//",
+            Operator::Power => "^",
+            Operator::And => "&",
+            Operator::Or => "|",
+            Operator::Xor => "xor",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+            Operator::RotateLeft => "rol",
+            Operator::RotateRight => "ror",
+            Operator::To => "to",
+            Operator::PercentChange => "chg",
+            Operator::Markup => "markup",
+            Operator::Margin => "margin",
+        }
+    }
+
+    /// The operator spelled as a word, such as `xor`.
+    pub fn from_word(word: &str) -> Option<Operator> {
+        [
+            Operator::Xor,
+            Operator::RotateLeft,
+            Operator::RotateRight,
+            Operator::To,
+            Operator::PercentChange,
+            Operator::Markup,
+            Operator::Margin,
+        ]
+        .into_iter()
+        .find(|operator| operator.symbol() == word)
+    }
+
+    /// Binding strength; higher binds tighter. The bitwise operators bind
+    /// more loosely than arithmetic, in C's order, and a unit conversion
+    /// loosest of all, so `1 km + 1 mi to m` converts the sum.
+    pub fn precedence(self) -> u8 {
+        match self {
+            Operator::To => 0,
+            Operator::Or => 1,
+            Operator::Xor => 2,
+            Operator::And => 3,
+            Operator::ShiftLeft
+            | Operator::ShiftRight
+            | Operator::RotateLeft
+            | Operator::RotateRight => 4,
+            Operator::Add | Operator::Subtract => 5,
+            Operator::Multiply
+            | Operator::Divide
+            | Operator::Modulo
+            | Operator::FloorDivide
+            | Operator::PercentChange
+            | Operator::Markup
+            | Operator::Margin => 6,
+            Operator::Power => 7,
+        }
+    }
+
+    /// Whether the operator only works on integers.
+    pub fn is_bitwise(self) -> bool {
+        (1..=4).contains(&self.precedence())
+    }
+
+    /// Whether a chain groups from the right: `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`.
+    pub fn is_right_associative(self) -> bool {
+        self == Operator::Power
+    }
+}
+
+/// Scientific functions of one argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    /// Base-10 logarithm.
+    Log,
+    /// Natural logarithm.
+    Ln,
+    Sqrt,
+    Exp,
+    /// Bitwise NOT, for the integer backend.
+    Not,
+}
+
+impl Function {
+    pub const ALL: [Function; 11] = [
+        Function::Sin,
+        Function::Cos,
+        Function::Tan,
+        Function::Asin,
+        Function::Acos,
+        Function::Atan,
+        Function::Log,
+        Function::Ln,
+        Function::Sqrt,
+        Function::Exp,
+        Function::Not,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Asin => "asin",
+            Function::Acos => "acos",
+            Function::Atan => "atan",
+            Function::Log => "log",
+            Function::Ln => "ln",
+            Function::Sqrt => "sqrt",
+            Function::Exp => "exp",
+            Function::Not => "not",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Function> {
+        Function::ALL
+            .into_iter()
+            .find(|function| function.name() == name)
+    }
+}
+
+/// Splits a typed expression such as `(2 + 3) × 4` into tokens.
+///
+/// Accepts the same operator spellings as the keyboard (`*`, `x`, `/`, `:`,
+/// `^`, `%`, `//`, `&`, `|`, `<<`, `>>`) plus the display symbols `×`, `·`
+/// and `÷` and the words `xor`, `rol`, `ror`, `to`, `chg`, `markup` and
+/// `margin`, so anything `expression_line` renders can be read back.
+/// Integers can be written in hex, octal or binary as `0xFF`, `0o17` or
+/// `0b101`.
+///
+/// A `%` followed by an operand is the remainder, as in `7 % 3`; anywhere
+/// else it is a percentage, as in `200 + 10%` or `10% × 3`.
+///
+/// Words are function names (`sqrt(2)`, `sin 30`), units and currencies in
+/// `rates`, or variable names. A unit directly after an operand is its
+/// unit, as in `5 km`, while any other name there multiplies, as in
+/// `2 rate` or `3 sqrt 4`. A lone
+/// `x` directly after an operand is still the multiplication sign, so `2x3`
+/// and `2 x 3` keep working while `x + 1` refers to a variable called `x`
+/// and `2 * xmax` or `3 x x1` to ones called `xmax` and `x1`.
+pub fn tokenize(text: &str, rates: &Rates) -> Result<Vec<Token>, ErrorKind> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut spaced = true;
+
+    while let Some(ch) = chars.next() {
+        let attached = !std::mem::replace(&mut spaced, ch.is_whitespace());
+        let token = match ch {
+            ch if ch.is_whitespace() => continue,
+            '0' if starts_prefixed_literal(&chars) => {
+                let mut literal = String::from(ch);
+                literal.extend(chars.next());
+                while let Some(&next) = chars.peek() {
+                    if !next.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    literal.push(next);
+                    chars.next();
+                }
+                let (radix, digits) =
+                    Radix::strip_prefix(&literal).ok_or(ErrorKind::InvalidNumber)?;
+                if u128::from_str_radix(digits, radix.base()).is_err() {
+                    return Err(ErrorKind::InvalidNumber);
+                }
+                Token::Number(literal)
+            }
+            ch if ch.is_ascii_digit() || ch == '.' => {
+                let mut number = String::from(ch);
+                while let Some(&next) = chars.peek() {
+                    if !next.is_ascii_digit() && next != '.' {
+                        break;
+                    }
+                    number.push(next);
+                    chars.next();
+                }
+                if number.parse::<f64>().is_err() {
+                    return Err(ErrorKind::InvalidNumber);
+                }
+                Token::Number(number)
+            }
+            '∞' => Token::Number(ch.to_string()),
+            'x' | 'X' if ends_with_operand(&tokens) && !starts_name(&chars, attached) => {
+                Token::Operator(Operator::Multiply)
+            }
+            ch if ch.is_alphabetic() || ch == '_' => {
+                let mut name = String::from(ch);
+                while let Some(&next) = chars.peek() {
+                    if !next.is_alphanumeric() && next != '_' {
+                        break;
+                    }
+                    name.push(next);
+                    chars.next();
+                }
+                if let Some(operator) = Operator::from_word(&name) {
+                    tokens.push(Token::Operator(operator));
+                    continue;
+                }
+                // a name after an operand multiplies it, as in `2 rate`,
+                // unless it is the operand's unit, as in `5 km`
+                if ends_with_operand(&tokens) && Unit::named(&name, rates).is_none() {
+                    tokens.push(Token::Operator(Operator::Multiply));
+                }
+                match (parse_answer(&name), Function::from_name(&name)) {
+                    (Some(back), _) => Token::Answer(back),
+                    (None, Some(function)) => Token::Function(function),
+                    (None, None) => Token::Variable(name),
+                }
+            }
+            '+' => Token::Operator(Operator::Add),
+            '-' if ends_with_operand(&tokens) => Token::Operator(Operator::Subtract),
+            '-' => Token::Negate,
+            '*' | '×' | '·' => Token::Operator(Operator::Multiply),
+            '/' if chars.next_if_eq(&'/').is_some() => Token::Operator(Operator::FloorDivide),
+            '/' | ':' | '÷' => Token::Operator(Operator::Divide),
+            '%' if starts_operand(&chars) => Token::Operator(Operator::Modulo),
+            '%' => Token::Percent,
+            '&' => Token::Operator(Operator::And),
+            '|' => Token::Operator(Operator::Or),
+            '<' if chars.next_if_eq(&'<').is_some() => Token::Operator(Operator::ShiftLeft),
+            '>' if chars.next_if_eq(&'>').is_some() => Token::Operator(Operator::ShiftRight),
+            '^' => Token::Operator(Operator::Power),
+            '(' => {
+                if ends_with_operand(&tokens) {
+                    tokens.push(Token::Operator(Operator::Multiply));
+                }
+                Token::LeftParen
+            }
+            ')' => Token::RightParen,
+            _ => return Err(ErrorKind::UnexpectedCharacter),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Whether an `x` after an operand, followed by `chars`, starts a name such
+/// as `xmax`, `x1` or `xor` rather than being the multiplication sign.
+/// Written against the operand, as in `2x3`, a digit after it starts the
+/// next number instead.
+fn starts_name(chars: &Peekable<Chars>, attached: bool) -> bool {
+    match chars.clone().peek() {
+        Some(next) if next.is_ascii_digit() => !attached,
+        Some(next) => next.is_alphanumeric() || *next == '_',
+        None => false,
+    }
+}
+
+/// Whether `chars` continue, after any spaces, with the start of an operand
+/// rather than an operator or the end. A lone `x` is the multiplication
+/// sign there, and a `-` written against an operand, as in `-3`, is a sign.
+fn starts_operand(chars: &Peekable<Chars>) -> bool {
+    let mut ahead = chars.clone();
+    while ahead.next_if(|next| next.is_whitespace()).is_some() {}
+    if ahead.next_if_eq(&'-').is_some() {
+        return ahead.peek().is_some_and(|next| !next.is_whitespace()) && starts_operand(&ahead);
+    }
+    match ahead.peek() {
+        Some(next) if next.is_alphabetic() || *next == '_' => {
+            let word: String = ahead
+                .take_while(|next| next.is_alphanumeric() || *next == '_')
+                .collect();
+            Operator::from_word(&word).is_none() && word != "x" && word != "X"
+        }
+        Some(next) => next.is_ascii_digit() || matches!(next, '.' | '(' | '∞'),
+        None => false,
+    }
+}
+
+/// Whether `chars`, just after a `0`, continue with a radix prefix letter
+/// and a digit, as in `0xFF`. A `0x` followed by anything else is still zero
+/// times something.
+fn starts_prefixed_literal(chars: &Peekable<Chars>) -> bool {
+    let mut ahead = chars.clone();
+    matches!(ahead.next(), Some('x' | 'o' | 'b'))
+        && ahead
+            .next()
+            .is_some_and(|next| next.is_ascii_alphanumeric())
+}
+
+/// Whether the last token completes an operand, so that what follows is a
+/// binary operator rather than the start of the next operand.
+pub(crate) fn ends_with_operand(tokens: &[Token]) -> bool {
+    matches!(
+        tokens.last(),
+        Some(
+            Token::Number(_)
+                | Token::Variable(_)
+                | Token::Answer(_)
+                | Token::Percent
+                | Token::RightParen
+        )
+    )
+}
+
+/// How many results back `name` refers to, if it is `Ans` or `Ans1`,
+/// `Ans2` and so on.
+pub(crate) fn parse_answer(name: &str) -> Option<usize> {
+    match name.strip_prefix("Ans")? {
+        "" => Some(0),
+        back if !back.starts_with('0') && back.bytes().all(|byte| byte.is_ascii_digit()) => {
+            back.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/// The name [`parse_answer`] reads back for the result `back` results
+/// before the last one.
+pub(crate) fn answer_name(back: usize) -> String {
+    match back {
+        0 => "Ans".into(),
+        back => format!("Ans{back}"),
+    }
+}
+
+/// Whether `name` can be used as a variable name: a letter or `_` followed
+/// by letters, digits or `_`, and not the name of a [`Function`], an
+/// operator word or an earlier result such as `Ans`.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_alphabetic() || first == '_')
+        && chars.all(|ch| ch.is_alphanumeric() || ch == '_')
+        && Function::from_name(name).is_none()
+        && Operator::from_word(name).is_none()
+        && parse_answer(name).is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_accepts_keyboard_and_display_symbols() {
+        assert_eq!(
+            tokenize("(2 + 3) × 4", &Rates::default()).unwrap(),
+            tokenize("(2+3)*4", &Rates::default()).unwrap()
+        );
+        assert_eq!(
+            tokenize("2(1)", &Rates::default()).unwrap(),
+            vec![
+                Token::Number("2".into()),
+                Token::Operator(Operator::Multiply),
+                Token::LeftParen,
+                Token::Number("1".into()),
+                Token::RightParen,
+            ]
+        );
+
+        assert_eq!(
+            tokenize("2x3", &Rates::default()).unwrap(),
+            tokenize("2 × 3", &Rates::default()).unwrap()
+        );
+        assert_eq!(
+            tokenize("7 // 2 % 3 ^ 2", &Rates::default()).unwrap(),
+            vec![
+                Token::Number("7".into()),
+                Token::Operator(Operator::FloorDivide),
+                Token::Number("2".into()),
+                Token::Operator(Operator::Modulo),
+                Token::Number("3".into()),
+                Token::Operator(Operator::Power),
+                Token::Number("2".into()),
+            ]
+        );
+        assert!(tokenize("2 $ 3", &Rates::default()).is_err());
+        assert!(tokenize("1.2.3", &Rates::default()).is_err());
+    }
+
+    #[test]
+    fn tokenize_tells_percent_from_remainder() {
+        assert_eq!(
+            tokenize("200 + 10% - 7 % 3", &Rates::default()).unwrap(),
+            vec![
+                Token::Number("200".into()),
+                Token::Operator(Operator::Add),
+                Token::Number("10".into()),
+                Token::Percent,
+                Token::Operator(Operator::Subtract),
+                Token::Number("7".into()),
+                Token::Operator(Operator::Modulo),
+                Token::Number("3".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize("5%(2)", &Rates::default()).unwrap()[1],
+            Token::Operator(Operator::Modulo)
+        );
+        assert_eq!(
+            tokenize("5 % rate", &Rates::default()).unwrap()[1],
+            Token::Operator(Operator::Modulo)
+        );
+        assert_eq!(
+            tokenize("7 % -3", &Rates::default()).unwrap()[1..3],
+            [Token::Operator(Operator::Modulo), Token::Negate]
+        );
+        assert_eq!(
+            tokenize("5% x 2", &Rates::default()).unwrap()[1],
+            Token::Percent
+        );
+        assert_eq!(
+            tokenize("5% to EUR", &Rates::default()).unwrap()[1],
+            Token::Percent
+        );
+        assert_eq!(
+            tokenize("80 chg 100", &Rates::default()).unwrap()[1],
+            Token::Operator(Operator::PercentChange)
+        );
+    }
+
+    #[test]
+    fn tokenize_reads_unary_minus() {
+        assert_eq!(
+            tokenize("2 - -3", &Rates::default()).unwrap(),
+            vec![
+                Token::Number("2".into()),
+                Token::Operator(Operator::Subtract),
+                Token::Negate,
+                Token::Number("3".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize("-(1)", &Rates::default()).unwrap(),
+            vec![
+                Token::Negate,
+                Token::LeftParen,
+                Token::Number("1".into()),
+                Token::RightParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_reads_function_names() {
+        assert_eq!(
+            tokenize("sqrt(2) + sin 30", &Rates::default()).unwrap(),
+            vec![
+                Token::Function(Function::Sqrt),
+                Token::LeftParen,
+                Token::Number("2".into()),
+                Token::RightParen,
+                Token::Operator(Operator::Add),
+                Token::Function(Function::Sin),
+                Token::Number("30".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize("2 ln(x)", &Rates::default()).unwrap()[..2],
+            [
+                Token::Number("2".into()),
+                Token::Operator(Operator::Multiply),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_reads_radix_literals_and_bitwise_operators() {
+        assert_eq!(
+            tokenize("0xFF & 0b1010 << 2 xor not 0o17", &Rates::default()).unwrap(),
+            vec![
+                Token::Number("0xFF".into()),
+                Token::Operator(Operator::And),
+                Token::Number("0b1010".into()),
+                Token::Operator(Operator::ShiftLeft),
+                Token::Number("2".into()),
+                Token::Operator(Operator::Xor),
+                Token::Function(Function::Not),
+                Token::Number("0o17".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize("0 x 3", &Rates::default()).unwrap(),
+            tokenize("0 × 3", &Rates::default()).unwrap()
+        );
+        assert!(tokenize("0b102", &Rates::default()).is_err());
+        assert!(tokenize("1 < 2", &Rates::default()).is_err());
+        assert!(!is_identifier("xor"));
+    }
+
+    #[test]
+    fn tokenize_reads_variable_names() {
+        assert_eq!(
+            tokenize("x x rate_2", &Rates::default()).unwrap(),
+            vec![
+                Token::Variable("x".into()),
+                Token::Operator(Operator::Multiply),
+                Token::Variable("rate_2".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize("2 x 3", &Rates::default()).unwrap(),
+            tokenize("2 × 3", &Rates::default()).unwrap()
+        );
+        assert_eq!(
+            tokenize("2x3", &Rates::default()).unwrap(),
+            tokenize("2 × 3", &Rates::default()).unwrap()
+        );
+        assert_eq!(
+            tokenize("(1)x(2)", &Rates::default()).unwrap(),
+            tokenize("(1) × (2)", &Rates::default()).unwrap()
+        );
+        assert_eq!(
+            tokenize("2 xval", &Rates::default()).unwrap(),
+            vec![
+                Token::Number("2".into()),
+                Token::Operator(Operator::Multiply),
+                Token::Variable("xval".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize("2 rate + 5 km", &Rates::default()).unwrap(),
+            vec![
+                Token::Number("2".into()),
+                Token::Operator(Operator::Multiply),
+                Token::Variable("rate".into()),
+                Token::Operator(Operator::Add),
+                Token::Number("5".into()),
+                Token::Variable("km".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize("3 x x1 + (1) xa", &Rates::default()).unwrap(),
+            vec![
+                Token::Number("3".into()),
+                Token::Operator(Operator::Multiply),
+                Token::Variable("x1".into()),
+                Token::Operator(Operator::Add),
+                Token::LeftParen,
+                Token::Number("1".into()),
+                Token::RightParen,
+                Token::Operator(Operator::Multiply),
+                Token::Variable("xa".into()),
+            ]
+        );
+        assert!(is_identifier("total"));
+        assert!(!is_identifier("2fast"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("sqrt"));
+    }
+
+    #[test]
+    fn tokenize_reads_earlier_results() {
+        assert_eq!(
+            tokenize("Ans x 2 + Ans12", &Rates::default()).unwrap(),
+            vec![
+                Token::Answer(0),
+                Token::Operator(Operator::Multiply),
+                Token::Number("2".into()),
+                Token::Operator(Operator::Add),
+                Token::Answer(12),
+            ]
+        );
+        assert_eq!(answer_name(12), "Ans12");
+        assert_eq!(
+            tokenize("Ans0 answer", &Rates::default()).unwrap()[0],
+            Token::Variable("Ans0".into())
+        );
+        assert!(!is_identifier("Ans"));
+        assert!(!is_identifier("Ans3"));
+        assert!(is_identifier("Answer"));
+    }
+}
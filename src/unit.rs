@@ -0,0 +1,543 @@
+//! Units of measure, for quantities such as `5 km` or `60 km/h`.
+//!
+//! A [`Unit`] is a product of named units with integer powers. Each named
+//! unit has a size in SI base units, written as exact number text so that
+//! conversions stay exact in the decimal and rational backends. Currencies
+//! come from the exchange [`Rates`] rather than the built-in table.
+
+use std::{borrow::Cow, fmt};
+
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+
+use crate::{
+    currency::Rates,
+    error::ErrorKind,
+    token::{Operator, Token},
+    value::{Backend, Value},
+};
+
+/// Powers of length, mass, time, electric current, temperature, amount of
+/// substance, information and money, in that order.
+type Dimension = [i8; 8];
+
+/// The largest power of a named unit, as in `m^127`, so that sizes stay
+/// quick to compute.
+pub(crate) const MAX_UNIT_POWER: i32 = i8::MAX as i32;
+
+const LENGTH: Dimension = [1, 0, 0, 0, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0, 0, 0, 0];
+const CURRENT: Dimension = [0, 0, 0, 1, 0, 0, 0, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1, 0, 0, 0];
+const AMOUNT: Dimension = [0, 0, 0, 0, 0, 1, 0, 0];
+const DATA: Dimension = [0, 0, 0, 0, 0, 0, 1, 0];
+const AREA: Dimension = [2, 0, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dimension = [3, 0, 0, 0, 0, 0, 0, 0];
+const SPEED: Dimension = [1, 0, -1, 0, 0, 0, 0, 0];
+const FREQUENCY: Dimension = [0, 0, -1, 0, 0, 0, 0, 0];
+const FORCE: Dimension = [1, 1, -2, 0, 0, 0, 0, 0];
+const PRESSURE: Dimension = [-1, 1, -2, 0, 0, 0, 0, 0];
+const ENERGY: Dimension = [2, 1, -2, 0, 0, 0, 0, 0];
+const POWER: Dimension = [2, 1, -3, 0, 0, 0, 0, 0];
+const MONEY: Dimension = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// A named unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Definition {
+    symbol: Cow<'static, str>,
+    /// Size in SI base units (bits for information, the base currency for
+    /// money), as exact number text.
+    factor: Cow<'static, str>,
+    dimension: Dimension,
+    /// For temperature scales with their own zero, what to add before
+    /// scaling to kelvin.
+    offset: Option<&'static str>,
+}
+
+const fn unit(symbol: &'static str, factor: &'static str, dimension: Dimension) -> Definition {
+    Definition {
+        symbol: Cow::Borrowed(symbol),
+        factor: Cow::Borrowed(factor),
+        dimension,
+        offset: None,
+    }
+}
+
+/// Every named unit, grouped by what it measures.
+const UNITS: &[Definition] = &[
+    unit("m", "1", LENGTH),
+    unit("km", "1000", LENGTH),
+    unit("cm", "0.01", LENGTH),
+    unit("mm", "0.001", LENGTH),
+    unit("um", "0.000001", LENGTH),
+    unit("nm", "0.000000001", LENGTH),
+    unit("in", "0.0254", LENGTH),
+    unit("ft", "0.3048", LENGTH),
+    unit("yd", "0.9144", LENGTH),
+    unit("mi", "1609.344", LENGTH),
+    unit("nmi", "1852", LENGTH),
+    unit("kg", "1", MASS),
+    unit("g", "0.001", MASS),
+    unit("mg", "0.000001", MASS),
+    unit("t", "1000", MASS),
+    unit("oz", "0.028349523125", MASS),
+    unit("lb", "0.45359237", MASS),
+    unit("st", "6.35029318", MASS),
+    unit("s", "1", TIME),
+    unit("ms", "0.001", TIME),
+    unit("us", "0.000001", TIME),
+    unit("ns", "0.000000001", TIME),
+    unit("min", "60", TIME),
+    unit("h", "3600", TIME),
+    unit("d", "86400", TIME),
+    unit("wk", "604800", TIME),
+    // the Julian year of 365.25 days
+    unit("yr", "31557600", TIME),
+    unit("A", "1", CURRENT),
+    unit("mA", "0.001", CURRENT),
+    unit("K", "1", TEMPERATURE),
+    Definition {
+        symbol: Cow::Borrowed("degC"),
+        factor: Cow::Borrowed("1"),
+        dimension: TEMPERATURE,
+        offset: Some("273.15"),
+    },
+    Definition {
+        symbol: Cow::Borrowed("degF"),
+        factor: Cow::Borrowed("5/9"),
+        dimension: TEMPERATURE,
+        offset: Some("459.67"),
+    },
+    unit("mol", "1", AMOUNT),
+    unit("bit", "1", DATA),
+    unit("kbit", "1000", DATA),
+    unit("Mbit", "1000000", DATA),
+    unit("Gbit", "1000000000", DATA),
+    unit("B", "8", DATA),
+    unit("kB", "8000", DATA),
+    unit("MB", "8000000", DATA),
+    unit("GB", "8000000000", DATA),
+    unit("TB", "8000000000000", DATA),
+    unit("KiB", "8192", DATA),
+    unit("MiB", "8388608", DATA),
+    unit("GiB", "8589934592", DATA),
+    unit("TiB", "8796093022208", DATA),
+    unit("ha", "10000", AREA),
+    unit("acre", "4046.8564224", AREA),
+    unit("L", "0.001", VOLUME),
+    unit("mL", "0.000001", VOLUME),
+    unit("gal", "0.003785411784", VOLUME),
+    unit("qt", "0.000946352946", VOLUME),
+    unit("pt", "0.000473176473", VOLUME),
+    unit("mph", "0.44704", SPEED),
+    unit("kn", "1852/3600", SPEED),
+    unit("Hz", "1", FREQUENCY),
+    unit("kHz", "1000", FREQUENCY),
+    unit("MHz", "1000000", FREQUENCY),
+    unit("GHz", "1000000000", FREQUENCY),
+    unit("N", "1", FORCE),
+    unit("lbf", "4.4482216152605", FORCE),
+    unit("Pa", "1", PRESSURE),
+    unit("kPa", "1000", PRESSURE),
+    unit("bar", "100000", PRESSURE),
+    unit("atm", "101325", PRESSURE),
+    unit("psi", "6894.757293168361", PRESSURE),
+    unit("J", "1", ENERGY),
+    unit("kJ", "1000", ENERGY),
+    unit("cal", "4.184", ENERGY),
+    unit("kcal", "4184", ENERGY),
+    unit("Wh", "3600", ENERGY),
+    unit("kWh", "3600000", ENERGY),
+    unit("W", "1", POWER),
+    unit("kW", "1000", POWER),
+    unit("MW", "1000000", POWER),
+    unit("hp", "745.69987158227022", POWER),
+];
+
+/// A product of named units with integer powers, such as `km/h` or `m^2`.
+/// The empty unit is that of a plain number.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Unit {
+    /// Named units and their nonzero powers, in the order first used.
+    parts: Vec<(Definition, i32)>,
+}
+
+impl Unit {
+    /// The unit with symbol `symbol`, such as `km`, or the currency with
+    /// that code in `rates`.
+    pub fn named(symbol: &str, rates: &Rates) -> Option<Unit> {
+        match UNITS.iter().find(|unit| unit.symbol == symbol) {
+            Some(definition) => Some(Unit {
+                parts: vec![(definition.clone(), 1)],
+            }),
+            None => rates.unit(symbol),
+        }
+    }
+
+    /// The currency `code` whose size is `size` units of the base currency.
+    pub(crate) fn currency(code: &str, size: &BigRational) -> Unit {
+        let definition = Definition {
+            symbol: Cow::Owned(code.to_string()),
+            factor: Cow::Owned(size.to_string()),
+            dimension: MONEY,
+            offset: None,
+        };
+        Unit {
+            parts: vec![(definition, 1)],
+        }
+    }
+
+    /// Parses a unit as it is displayed, such as `kg·m/s^2`.
+    pub fn parse(text: &str, rates: &Rates) -> Option<Unit> {
+        let mut unit = Unit::default();
+        for (index, group) in text.split('/').enumerate() {
+            let sign = if index == 0 { 1 } else { -1 };
+            for part in group.split('·') {
+                let (symbol, power) = match part.split_once('^') {
+                    Some((symbol, power)) => (symbol, power.parse().ok()?),
+                    None => (part, 1),
+                };
+                let (definition, _) = Unit::named(symbol, rates)?.parts.pop()?;
+                unit.push(definition, sign * power).ok()?;
+            }
+        }
+        Some(unit)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    fn dimension(&self) -> [i32; 8] {
+        let mut dimension = [0; 8];
+        for (definition, power) in &self.parts {
+            for (total, base) in dimension.iter_mut().zip(definition.dimension) {
+                *total += i32::from(base) * power;
+            }
+        }
+        dimension
+    }
+
+    /// Whether quantities in the two units can be added or converted.
+    pub fn is_compatible(&self, other: &Unit) -> bool {
+        self.dimension() == other.dimension()
+    }
+
+    /// Whether the unit measures nothing, like `km/m`, so that a quantity
+    /// in it is a plain number.
+    pub(crate) fn is_dimensionless(&self) -> bool {
+        self.dimension() == [0; 8]
+    }
+
+    /// Whether the unit involves a currency, as in `USD` or `EUR/h`.
+    pub fn is_money(&self) -> bool {
+        self.dimension()[7] != 0
+    }
+
+    /// Multiplies in `definition` to the power `power`, failing when that
+    /// takes it beyond [`MAX_UNIT_POWER`].
+    fn push(&mut self, definition: Definition, power: i32) -> Result<(), ErrorKind> {
+        let index = self.parts.iter().position(|(part, _)| *part == definition);
+        let total = match index {
+            Some(index) => self.parts[index].1.checked_add(power),
+            None => Some(power),
+        }
+        .filter(|total| total.abs() <= MAX_UNIT_POWER)
+        .ok_or(ErrorKind::OutOfRange)?;
+        match index {
+            Some(index) if total == 0 => {
+                self.parts.remove(index);
+            }
+            Some(index) => self.parts[index].1 = total,
+            None if total != 0 => self.parts.push((definition, total)),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// This unit times `other` to the power `sign` (`1` to multiply, `-1` to
+    /// divide), and the dimensionless unit whose size the value has to be
+    /// multiplied by. A unit of a kind already present is expressed in the
+    /// one already there, so `m × ft` is in `m^2` and `km/h × min` in `km`.
+    pub(crate) fn combine(&self, other: &Unit, sign: i32) -> Result<(Unit, Unit), ErrorKind> {
+        let mut unit = self.clone();
+        let mut conversion = Unit::default();
+        for (definition, power) in &other.parts {
+            let power = power * sign;
+            let target = unit
+                .parts
+                .iter()
+                .map(|(part, _)| part)
+                .find(|part| part.dimension == definition.dimension)
+                .unwrap_or(definition)
+                .clone();
+            if target != *definition {
+                conversion.push(definition.clone(), power)?;
+                conversion.push(target.clone(), -power)?;
+            }
+            unit.push(target, power)?;
+        }
+        Ok((unit, conversion))
+    }
+
+    pub(crate) fn powi(&self, power: i32) -> Result<Unit, ErrorKind> {
+        let mut unit = Unit::default();
+        for (definition, own) in &self.parts {
+            let power = own.checked_mul(power).ok_or(ErrorKind::OutOfRange)?;
+            unit.push(definition.clone(), power)?;
+        }
+        Ok(unit)
+    }
+
+    /// The square root, when every power is even.
+    pub(crate) fn sqrt(&self) -> Option<Unit> {
+        self.parts
+            .iter()
+            .all(|(_, power)| power % 2 == 0)
+            .then(|| Unit {
+                parts: self
+                    .parts
+                    .iter()
+                    .map(|(definition, power)| (definition.clone(), power / 2))
+                    .collect(),
+            })
+    }
+
+    /// The size of the unit in SI base units, exactly.
+    fn size(&self) -> BigRational {
+        self.parts
+            .iter()
+            .fold(BigRational::one(), |size, (definition, power)| {
+                size * exact(&definition.factor).pow(*power)
+            })
+    }
+
+    /// [`Unit::size`] as a rational [`Value`], e.g. for a dimensionless
+    /// unit such as `km/m` that stands for a plain number.
+    pub(crate) fn size_value(&self) -> Value {
+        Value::Rational(self.size())
+    }
+
+    /// What to add before scaling to kelvin, for a lone temperature scale
+    /// such as `degC`. Units such as `degC/s` measure differences and have
+    /// none.
+    fn offset(&self) -> Option<BigRational> {
+        match self.parts.as_slice() {
+            [(definition, 1)] if definition.dimension == TEMPERATURE => {
+                Some(definition.offset.map_or_else(BigRational::zero, exact))
+            }
+            _ => None,
+        }
+    }
+
+    /// The exact `factor` and `offset` that convert a value in this unit to
+    /// one in `target`, as `value × factor + offset`, or `None` when the
+    /// units measure different things. Only a conversion between
+    /// `absolute` temperatures has an offset: `20 degC` is `68 degF`, while
+    /// a difference of 20 °C is one of 36 °F.
+    pub(crate) fn conversion_to(
+        &self,
+        target: &Unit,
+        absolute: bool,
+    ) -> Option<(Value, Option<Value>)> {
+        if !self.is_compatible(target) {
+            return None;
+        }
+        let factor = self.size() / target.size();
+        let offset = match (absolute, self.offset(), target.offset()) {
+            (true, Some(from), Some(to)) => Some(from * &factor - to),
+            _ => None,
+        };
+        let offset = offset.filter(|offset| !offset.is_zero());
+        Some((Value::Rational(factor), offset.map(Value::Rational)))
+    }
+
+    /// The tokens of a quantity of `number` in this unit, as in `5 km`.
+    /// Compound units are grouped, `(60 km ÷ h)`, so that the quantity
+    /// stays one operand.
+    pub(crate) fn quantity_tokens(&self, number: String) -> Vec<Token> {
+        let mut tokens = vec![Token::Number(number)];
+        for (index, (definition, power)) in self.parts.iter().enumerate() {
+            let power = *power;
+            if index > 0 || power < 0 {
+                tokens.push(Token::Operator(if power < 0 {
+                    Operator::Divide
+                } else {
+                    Operator::Multiply
+                }));
+            }
+            tokens.push(Token::Variable(definition.symbol.to_string()));
+            if power.abs() != 1 {
+                tokens.push(Token::Operator(Operator::Power));
+                tokens.push(Token::Number(power.abs().to_string()));
+            }
+        }
+        if tokens.len() > 2 && !matches!(self.parts.as_slice(), [(_, 2..)]) {
+            tokens.insert(0, Token::LeftParen);
+            tokens.push(Token::RightParen);
+        }
+        tokens
+    }
+}
+
+/// `text` from the unit table or the rates as an exact fraction.
+fn exact(text: &str) -> BigRational {
+    match Value::parse(text, Backend::Rational) {
+        Ok(Value::Rational(value)) => value,
+        _ => unreachable!("unit table numbers are valid"),
+    }
+}
+
+/// Positive powers first, then each negative one after a `/`: `km/h`,
+/// `kg·m/s^2`. A unit with only negative powers keeps them, as in `s^-1`.
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_part = |f: &mut fmt::Formatter<'_>, definition: &Definition, power: i32| {
+            if power == 1 {
+                f.write_str(&definition.symbol)
+            } else {
+                write!(f, "{}^{power}", definition.symbol)
+            }
+        };
+
+        if self.parts.iter().all(|(_, power)| *power < 0) {
+            for (index, (definition, power)) in self.parts.iter().enumerate() {
+                if index > 0 {
+                    f.write_str("·")?;
+                }
+                write_part(f, definition, *power)?;
+            }
+            return Ok(());
+        }
+
+        let numerator = self.parts.iter().filter(|(_, power)| *power > 0);
+        for (index, (definition, power)) in numerator.enumerate() {
+            if index > 0 {
+                f.write_str("·")?;
+            }
+            write_part(f, definition, *power)?;
+        }
+        for (definition, power) in self.parts.iter().filter(|(_, power)| *power < 0) {
+            f.write_str("/")?;
+            write_part(f, definition, -power)?;
+        }
+        Ok(())
+    }
+}
+
+/// A value with a unit. Plain numbers have the empty unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: Value,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    pub fn negate(self) -> Quantity {
+        Quantity {
+            value: self.value.negate(),
+            unit: self.unit,
+        }
+    }
+
+    pub fn into_backend(self, backend: Backend) -> Quantity {
+        Quantity {
+            value: self.value.into_backend(backend),
+            unit: self.unit,
+        }
+    }
+}
+
+impl From<Value> for Quantity {
+    fn from(value: Value) -> Self {
+        Quantity {
+            value,
+            unit: Unit::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(text: &str) -> Unit {
+        Unit::parse(text, &Rates::default()).unwrap()
+    }
+
+    #[test]
+    fn units_display_and_parse_back() {
+        for text in ["km", "km/h", "m^2", "kg·m/s^2", "s^-1", "J/kg/K"] {
+            assert_eq!(unit(text).to_string(), text);
+        }
+        assert_eq!(Unit::parse("furlong", &Rates::default()), None);
+        assert_eq!(Unit::parse("m^x", &Rates::default()), None);
+    }
+
+    #[test]
+    fn combining_cancels_and_converts_units_of_a_kind() {
+        let (speed, conversion) = unit("km").combine(&unit("h"), -1).unwrap();
+        assert_eq!(speed.to_string(), "km/h");
+        assert!(conversion.is_empty());
+
+        let (distance, conversion) = speed.combine(&unit("min"), 1).unwrap();
+        assert_eq!(distance.to_string(), "km");
+        assert_eq!(conversion.to_string(), "min/h");
+
+        let (area, conversion) = unit("m").combine(&unit("ft"), 1).unwrap();
+        assert_eq!(area.to_string(), "m^2");
+        assert_eq!(conversion.to_string(), "ft/m");
+        assert!(unit("km/m").is_dimensionless());
+    }
+
+    #[test]
+    fn powers_stay_within_bounds() {
+        assert_eq!(unit("m^127").to_string(), "m^127");
+        assert_eq!(Unit::parse("m^128", &Rates::default()), None);
+        assert_eq!(
+            unit("m^100").combine(&unit("m^100"), 1),
+            Err(ErrorKind::OutOfRange)
+        );
+        assert_eq!(
+            unit("m^100").combine(&unit("m^100"), -1),
+            Ok(Default::default())
+        );
+        assert_eq!(unit("m^2").powi(i32::MAX), Err(ErrorKind::OutOfRange));
+        assert_eq!(unit("km/h").powi(-3).unwrap().to_string(), "h^3/km^3");
+    }
+
+    #[test]
+    fn compatibility_follows_dimensions() {
+        assert!(unit("km/h").is_compatible(&unit("mph")));
+        assert!(unit("kWh").is_compatible(&unit("J")));
+        assert!(unit("GiB").is_compatible(&unit("Mbit")));
+        assert!(!unit("m").is_compatible(&unit("s")));
+        assert!(!unit("m").is_compatible(&Unit::default()));
+    }
+
+    #[test]
+    fn conversions_are_exact() {
+        let conversion = |from: &str, to: &str, absolute: bool| {
+            let (factor, offset) = unit(from).conversion_to(&unit(to), absolute).unwrap();
+            (factor.to_string(), offset.map(|offset| offset.to_string()))
+        };
+        assert_eq!(conversion("mi", "km", true), ("25146/15625".into(), None));
+        assert_eq!(conversion("km/h", "kn", false), ("250/463".into(), None));
+        assert_eq!(
+            conversion("ft^2", "m^2", false),
+            ("145161/1562500".into(), None)
+        );
+        assert_eq!(
+            conversion("degC", "degF", true),
+            ("9/5".into(), Some("32".into()))
+        );
+        assert_eq!(conversion("degC", "degF", false), ("9/5".into(), None));
+        assert_eq!(
+            conversion("K", "degC", true),
+            ("1".into(), Some("-5463/20".into()))
+        );
+        assert_eq!(unit("m").conversion_to(&unit("s"), true), None);
+        assert_eq!(unit("km/m").size_value().to_string(), "1000");
+    }
+}
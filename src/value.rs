@@ -0,0 +1,1193 @@
+//! Numbers produced by the evaluator and the arithmetic backends behind them.
+
+use std::{fmt, num::NonZeroU64, str::FromStr};
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+pub use bigdecimal::RoundingMode;
+
+use crate::{
+    error::ErrorKind,
+    format::NumberFormat,
+    integer::{IntegerSettings, Radix},
+    token::{Function, Operator},
+};
+
+/// Significant digits kept by the decimal backend unless configured otherwise.
+pub const DEFAULT_DECIMAL_PRECISION: u64 = 34;
+
+/// Upper bound for the decimal precision; quotients are exact to this many
+/// digits before they are rounded.
+pub const MAX_DECIMAL_PRECISION: u64 = 100;
+
+/// Upper bound for the digits of an exact power, so that `10 ^ 999999999`
+/// fails at once instead of exhausting memory.
+const MAX_POWER_DIGITS: u64 = 10_000;
+
+/// How `Token::Number` texts are turned into values and combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// IEEE 754 double precision, fast but binary (`0.1 + 0.2` drifts).
+    #[default]
+    Float,
+    /// Exact base-10 values rounded to [`DecimalSettings::precision`]
+    /// significant digits after every operation.
+    Decimal,
+    /// Exact fractions of big integers; `1 ÷ 3 × 3` is exactly `1`.
+    Rational,
+    /// Fixed-width integers that wrap like machine words, for programmer
+    /// work with [`IntegerSettings`] and the bitwise operators.
+    Integer,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Float => "f64",
+            Backend::Decimal => "decimal",
+            Backend::Rational => "rational",
+            Backend::Integer => "integer",
+        }
+    }
+
+    /// The backend after this one when cycling with a key.
+    pub fn next(self) -> Backend {
+        match self {
+            Backend::Float => Backend::Decimal,
+            Backend::Decimal => Backend::Rational,
+            Backend::Rational => Backend::Integer,
+            Backend::Integer => Backend::Float,
+        }
+    }
+}
+
+/// Precision and rounding used by [`Backend::Decimal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalSettings {
+    /// Significant digits, between 1 and [`MAX_DECIMAL_PRECISION`].
+    pub precision: u64,
+    pub rounding: RoundingMode,
+}
+
+impl Default for DecimalSettings {
+    fn default() -> Self {
+        Self {
+            precision: DEFAULT_DECIMAL_PRECISION,
+            rounding: RoundingMode::HalfEven,
+        }
+    }
+}
+
+impl DecimalSettings {
+    fn round(&self, value: BigDecimal) -> BigDecimal {
+        let precision = self.precision.clamp(1, MAX_DECIMAL_PRECISION);
+        let precision = NonZeroU64::new(precision).unwrap_or(NonZeroU64::MIN);
+        value.with_precision_round(precision, self.rounding)
+    }
+}
+
+/// Short lowercase name of a rounding mode, e.g. `half-even`.
+pub fn rounding_mode_name(mode: RoundingMode) -> &'static str {
+    match mode {
+        RoundingMode::Up => "up",
+        RoundingMode::Down => "down",
+        RoundingMode::Ceiling => "ceiling",
+        RoundingMode::Floor => "floor",
+        RoundingMode::HalfUp => "half-up",
+        RoundingMode::HalfDown => "half-down",
+        RoundingMode::HalfEven => "half-even",
+    }
+}
+
+/// Parses a name produced by [`rounding_mode_name`].
+pub fn parse_rounding_mode(name: &str) -> Option<RoundingMode> {
+    ROUNDING_MODES
+        .into_iter()
+        .find(|mode| rounding_mode_name(*mode) == name)
+}
+
+/// Every rounding mode, in the order the interactive calculator cycles them.
+pub const ROUNDING_MODES: [RoundingMode; 7] = [
+    RoundingMode::HalfEven,
+    RoundingMode::HalfUp,
+    RoundingMode::HalfDown,
+    RoundingMode::Up,
+    RoundingMode::Down,
+    RoundingMode::Ceiling,
+    RoundingMode::Floor,
+];
+
+/// How [`Backend::Rational`] results are shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FractionStyle {
+    /// `7/3`
+    #[default]
+    Improper,
+    /// `2 1/3`
+    Mixed,
+    /// `2.333…`, rounded like the decimal backend.
+    Decimal,
+}
+
+impl FractionStyle {
+    pub fn name(self) -> &'static str {
+        match self {
+            FractionStyle::Improper => "fraction",
+            FractionStyle::Mixed => "mixed",
+            FractionStyle::Decimal => "decimal",
+        }
+    }
+
+    pub fn next(self) -> FractionStyle {
+        match self {
+            FractionStyle::Improper => FractionStyle::Mixed,
+            FractionStyle::Mixed => FractionStyle::Decimal,
+            FractionStyle::Decimal => FractionStyle::Improper,
+        }
+    }
+}
+
+/// What the `f64` backend does with results that are not finite numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FloatMode {
+    /// Division by zero, domain errors, overflow to infinity and undefined
+    /// (NaN) results are errors.
+    #[default]
+    Checked,
+    /// IEEE 754 arithmetic: such results are kept as `∞`, `-∞` and `NaN`.
+    Ieee,
+}
+
+impl FloatMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            FloatMode::Checked => "checked",
+            FloatMode::Ieee => "ieee",
+        }
+    }
+
+    pub fn next(self) -> FloatMode {
+        match self {
+            FloatMode::Checked => FloatMode::Ieee,
+            FloatMode::Ieee => FloatMode::Checked,
+        }
+    }
+
+    /// `result`, or in checked mode the error a non-finite one stands for.
+    fn check(self, result: f64) -> Result<f64, ErrorKind> {
+        match self {
+            FloatMode::Checked if result.is_nan() => Err(ErrorKind::Undefined),
+            FloatMode::Checked if result.is_infinite() => Err(ErrorKind::Overflow),
+            _ => Ok(result),
+        }
+    }
+}
+
+/// Unit of the angles taken by `sin`, `cos` and `tan` and returned by their
+/// inverses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AngleUnit {
+    #[default]
+    Radians,
+    /// 360 to a full turn.
+    Degrees,
+    /// 400 to a full turn, as used in surveying.
+    Gradians,
+}
+
+impl AngleUnit {
+    pub fn name(self) -> &'static str {
+        match self {
+            AngleUnit::Radians => "rad",
+            AngleUnit::Degrees => "deg",
+            AngleUnit::Gradians => "grad",
+        }
+    }
+
+    pub fn next(self) -> AngleUnit {
+        match self {
+            AngleUnit::Radians => AngleUnit::Degrees,
+            AngleUnit::Degrees => AngleUnit::Gradians,
+            AngleUnit::Gradians => AngleUnit::Radians,
+        }
+    }
+
+    fn full_turn(self) -> f64 {
+        match self {
+            AngleUnit::Radians => std::f64::consts::TAU,
+            AngleUnit::Degrees => 360.0,
+            AngleUnit::Gradians => 400.0,
+        }
+    }
+
+    fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Radians => angle,
+            _ => angle / self.full_turn() * std::f64::consts::TAU,
+        }
+    }
+
+    fn radians_to_unit(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Radians => angle,
+            _ => angle * self.full_turn() / std::f64::consts::TAU,
+        }
+    }
+
+    /// Rounds a trigonometric result in degrees or gradians to 15
+    /// significant digits, dropping the last-digit error of converting
+    /// through π, so that `sin 30` is `0.5` and `asin 0.5` is `30`.
+    fn round_off(self, value: f64) -> f64 {
+        match self {
+            AngleUnit::Radians => value,
+            _ => format!("{value:.14e}").parse().unwrap_or(value),
+        }
+    }
+
+    /// For degrees and gradians, the position of `angle` among the four
+    /// right angles of a turn when it is an exact multiple of one, so that
+    /// `sin 180` is exactly `0` rather than a rounding residue of π.
+    fn quarter_turns(self, angle: f64) -> Option<usize> {
+        if self == AngleUnit::Radians {
+            return None;
+        }
+        let quarters = angle / (self.full_turn() / 4.0);
+        (quarters.fract() == 0.0).then(|| quarters.rem_euclid(4.0) as usize)
+    }
+}
+
+/// A number in one of the [`Backend`] representations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Float(f64),
+    Decimal(BigDecimal),
+    Rational(BigRational),
+    Integer(i128),
+}
+
+impl Value {
+    /// Parses a decimal number such as `2.5`, a fraction such as `7/3` as
+    /// printed for rational results, or an integer literal in another radix
+    /// such as `0xFF`. The integer backend only takes whole numbers.
+    pub fn parse(text: &str, backend: Backend) -> Result<Value, ErrorKind> {
+        if let Some((numerator, denominator)) = text.split_once('/') {
+            let numerator = Value::parse(numerator, backend)?;
+            let denominator = Value::parse(denominator, backend)?;
+            return numerator.apply(
+                Operator::Divide,
+                denominator,
+                &DecimalSettings::default(),
+                FloatMode::Checked,
+            );
+        }
+
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, text),
+        };
+        if let Some((radix, digits)) = Radix::strip_prefix(unsigned) {
+            let magnitude =
+                i128::from_str_radix(digits, radix.base()).map_err(|_| ErrorKind::InvalidNumber)?;
+            let value = if negative { -magnitude } else { magnitude };
+            return Ok(Value::Integer(value).into_backend(backend));
+        }
+
+        let decimal = || BigDecimal::from_str(text).map_err(|_| ErrorKind::InvalidNumber);
+        match backend {
+            // `∞` is how infinite results print
+            Backend::Float => text
+                .replace('∞', "inf")
+                .parse()
+                .map(Value::Float)
+                .map_err(|_| ErrorKind::InvalidNumber),
+            Backend::Decimal => decimal().map(Value::Decimal),
+            Backend::Rational => {
+                decimal().map(|value| Value::Rational(decimal_to_rational(&value)))
+            }
+            Backend::Integer => text
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| ErrorKind::InvalidNumber),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self {
+            Value::Float(_) => Backend::Float,
+            Value::Decimal(_) => Backend::Decimal,
+            Value::Rational(_) => Backend::Rational,
+            Value::Integer(_) => Backend::Integer,
+        }
+    }
+
+    /// Converts to `backend`, going through the shortest decimal text of a
+    /// float so `0.1` becomes exactly `0.1` (or `1/10`). Conversion to the
+    /// integer backend truncates toward zero.
+    pub fn into_backend(self, backend: Backend) -> Value {
+        match (self, backend) {
+            (value, Backend::Float) => Value::Float(value.to_f64()),
+            (Value::Float(value), Backend::Integer) => match value.trunc().to_i128() {
+                Some(integer) => Value::Integer(integer),
+                None => Value::Float(value),
+            },
+            (Value::Decimal(value), Backend::Integer) => {
+                match value.with_scale_round(0, RoundingMode::Down).to_i128() {
+                    Some(integer) => Value::Integer(integer),
+                    None => Value::Decimal(value),
+                }
+            }
+            (Value::Rational(value), Backend::Integer) => match value.to_integer().to_i128() {
+                Some(integer) => Value::Integer(integer),
+                None => Value::Rational(value),
+            },
+            (Value::Integer(value), Backend::Decimal) => Value::Decimal(BigDecimal::from(value)),
+            (Value::Integer(value), Backend::Rational) => {
+                Value::Rational(BigRational::from_integer(BigInt::from(value)))
+            }
+            (Value::Float(value), backend) => match BigDecimal::from_str(&value.to_string()) {
+                Ok(decimal) => Value::Decimal(decimal).into_backend(backend),
+                Err(_) => Value::Float(value),
+            },
+            (Value::Decimal(value), Backend::Rational) => {
+                Value::Rational(decimal_to_rational(&value))
+            }
+            (Value::Rational(value), Backend::Decimal) => {
+                Value::Decimal(rational_to_decimal(&value))
+            }
+            (value, _) => value,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Float(value) => *value,
+            Value::Decimal(value) => value.to_f64().unwrap_or(f64::NAN),
+            Value::Rational(value) => value.to_f64().unwrap_or(f64::NAN),
+            Value::Integer(value) => *value as f64,
+        }
+    }
+
+    pub fn negate(self) -> Value {
+        match self {
+            Value::Float(value) => Value::Float(-value),
+            Value::Decimal(value) => Value::Decimal(-value),
+            Value::Rational(value) => Value::Rational(-value),
+            Value::Integer(value) => Value::Integer(-value),
+        }
+    }
+
+    /// Combines two values with `operator`, in the backend of `self`.
+    ///
+    /// Integers wrap in a signed 64-bit word here; the engine applies its
+    /// configured [`IntegerSettings`] instead.
+    pub fn apply(
+        self,
+        operator: Operator,
+        rhs: Value,
+        settings: &DecimalSettings,
+        float: FloatMode,
+    ) -> Result<Value, ErrorKind> {
+        if operator.is_bitwise() && self.backend() != Backend::Integer {
+            return Err(ErrorKind::BitwiseNeedsInteger);
+        }
+
+        match self {
+            Value::Float(lhs) => apply_float(lhs, rhs.to_f64(), operator, float).map(Value::Float),
+            Value::Decimal(lhs) => {
+                let Value::Decimal(rhs) = rhs.into_backend(Backend::Decimal) else {
+                    return Err(ErrorKind::InvalidNumber);
+                };
+                apply_decimal(lhs, rhs, operator).map(|value| Value::Decimal(settings.round(value)))
+            }
+            Value::Rational(lhs) => {
+                let Value::Rational(rhs) = rhs.into_backend(Backend::Rational) else {
+                    return Err(ErrorKind::InvalidNumber);
+                };
+                apply_rational(lhs, rhs, operator).map(Value::Rational)
+            }
+            Value::Integer(lhs) => {
+                let Value::Integer(rhs) = rhs.into_backend(Backend::Integer) else {
+                    return Err(ErrorKind::InvalidNumber);
+                };
+                IntegerSettings::default()
+                    .apply(lhs, rhs, operator)
+                    .map(|(value, _)| Value::Integer(value))
+            }
+        }
+    }
+
+    /// Applies a scientific function, with angles in `angle` units.
+    ///
+    /// Square roots of decimals and of perfect-square fractions stay exact;
+    /// everything else is computed in `f64` and converted back to the
+    /// backend of `self`. In [`FloatMode::Ieee`] an `f64` argument outside
+    /// the domain gives `NaN` or an infinity instead of an error.
+    pub fn apply_function(
+        self,
+        function: Function,
+        settings: &DecimalSettings,
+        angle: AngleUnit,
+        float: FloatMode,
+    ) -> Result<Value, ErrorKind> {
+        if function == Function::Not {
+            return match self {
+                Value::Integer(value) => Ok(Value::Integer(!value)),
+                _ => Err(ErrorKind::BitwiseNeedsInteger),
+            };
+        }
+
+        let x = self.to_f64();
+        let quarter_turns = angle.quarter_turns(x);
+        let backend = self.backend();
+        let ieee = backend == Backend::Float && float == FloatMode::Ieee;
+        match function {
+            _ if ieee => {}
+            Function::Sqrt if x < 0.0 => return Err(ErrorKind::SquareRootOfNegative),
+            Function::Log | Function::Ln if x == 0.0 => return Err(ErrorKind::LogarithmOfZero),
+            Function::Log | Function::Ln if x < 0.0 => {
+                return Err(ErrorKind::LogarithmOfNegative);
+            }
+            Function::Asin if !(-1.0..=1.0).contains(&x) => {
+                return Err(ErrorKind::InverseSineOutOfDomain);
+            }
+            Function::Acos if !(-1.0..=1.0).contains(&x) => {
+                return Err(ErrorKind::InverseCosineOutOfDomain);
+            }
+            // cos is never exactly zero in f64, so radians compare against
+            // its rounding error at odd multiples of π/2
+            Function::Tan
+                if quarter_turns.map_or(angle.to_radians(x).cos().abs() < 1e-15, |quarters| {
+                    quarters % 2 == 1
+                }) =>
+            {
+                return Err(ErrorKind::TangentUndefined);
+            }
+            _ => {}
+        }
+
+        match (function, self) {
+            (Function::Sqrt, Value::Decimal(value)) => {
+                if let Some(root) = value.sqrt() {
+                    return Ok(Value::Decimal(settings.round(root)));
+                }
+            }
+            (Function::Sqrt, Value::Rational(value)) => {
+                let numer = value.numer().sqrt();
+                let denom = value.denom().sqrt();
+                if &(&numer * &numer) == value.numer() && &(&denom * &denom) == value.denom() {
+                    return Ok(Value::Rational(BigRational::new(numer, denom)));
+                }
+            }
+            _ => {}
+        }
+
+        let radians = angle.to_radians(x);
+        let result = match function {
+            Function::Sin => {
+                quarter_turns.map_or(radians.sin(), |quarters| [0.0, 1.0, 0.0, -1.0][quarters])
+            }
+            Function::Cos => {
+                quarter_turns.map_or(radians.cos(), |quarters| [1.0, 0.0, -1.0, 0.0][quarters])
+            }
+            Function::Tan => quarter_turns.map_or(radians.tan(), |quarters| {
+                // only reached in IEEE mode for odd quarters
+                if quarters % 2 == 1 { f64::NAN } else { 0.0 }
+            }),
+            Function::Asin => angle.radians_to_unit(x.asin()),
+            Function::Acos => angle.radians_to_unit(x.acos()),
+            Function::Atan => angle.radians_to_unit(x.atan()),
+            Function::Log => x.log10(),
+            Function::Ln => x.ln(),
+            Function::Sqrt => x.sqrt(),
+            Function::Exp => x.exp(),
+            Function::Not => unreachable!("bitwise NOT is handled above"),
+        };
+        let result = match function {
+            Function::Sin
+            | Function::Cos
+            | Function::Tan
+            | Function::Asin
+            | Function::Acos
+            | Function::Atan => angle.round_off(result),
+            _ => result,
+        };
+        match backend {
+            Backend::Float => float.check(result).map(Value::Float),
+            _ if !result.is_finite() => Err(ErrorKind::OutOfRange),
+            Backend::Decimal => match Value::Float(result).into_backend(backend) {
+                Value::Decimal(value) => Ok(Value::Decimal(settings.round(value))),
+                value => Ok(value),
+            },
+            Backend::Rational => Ok(Value::Float(result).into_backend(backend)),
+            Backend::Integer => match Value::Float(result).into_backend(backend) {
+                Value::Integer(value) => Ok(Value::Integer(value)),
+                _ => Err(ErrorKind::OutOfRange),
+            },
+        }
+    }
+
+    /// Formats for display. Rationals follow `style`, and decimal text
+    /// follows `number`, with fixed decimals rounded like the decimal
+    /// backend.
+    pub fn format(
+        &self,
+        style: FractionStyle,
+        settings: &DecimalSettings,
+        number: &NumberFormat,
+    ) -> String {
+        number.apply(&self.format_fraction(style, settings), settings.rounding)
+    }
+
+    fn format_fraction(&self, style: FractionStyle, settings: &DecimalSettings) -> String {
+        let Value::Rational(value) = self else {
+            return self.to_string();
+        };
+
+        match style {
+            FractionStyle::Improper => self.to_string(),
+            FractionStyle::Mixed => {
+                // integer division truncates, so the remainder keeps the sign
+                let whole = value.numer() / value.denom();
+                let remainder = value.numer() % value.denom();
+                if whole.is_zero() || remainder.is_zero() {
+                    self.to_string()
+                } else {
+                    format!("{whole} {}/{}", remainder.abs(), value.denom())
+                }
+            }
+            FractionStyle::Decimal => {
+                Value::Decimal(settings.round(rational_to_decimal(value))).to_string()
+            }
+        }
+    }
+}
+
+fn decimal_to_rational(value: &BigDecimal) -> BigRational {
+    let (digits, scale) = value.as_bigint_and_exponent();
+    let power = BigInt::from(10).pow(scale.unsigned_abs() as u32);
+    if scale >= 0 {
+        BigRational::new(digits, power)
+    } else {
+        BigRational::from_integer(digits * power)
+    }
+}
+
+fn rational_to_decimal(value: &BigRational) -> BigDecimal {
+    BigDecimal::from(value.numer().clone()) / BigDecimal::from(value.denom().clone())
+}
+
+fn apply_float(lhs: f64, rhs: f64, operator: Operator, mode: FloatMode) -> Result<f64, ErrorKind> {
+    let divides = matches!(
+        operator,
+        Operator::Divide | Operator::Modulo | Operator::FloorDivide
+    );
+    // only an exact zero: tiny divisors such as 1e-20 are fine
+    if divides && rhs == 0.0 && mode == FloatMode::Checked {
+        return Err(ErrorKind::DivideByZero);
+    }
+
+    let result = match operator {
+        Operator::Add => lhs + rhs,
+        Operator::Subtract => lhs - rhs,
+        Operator::Multiply => lhs * rhs,
+        Operator::Divide => lhs / rhs,
+        Operator::Modulo => lhs - rhs * (lhs / rhs).floor(),
+        Operator::FloorDivide => (lhs / rhs).floor(),
+        Operator::Power if mode == FloatMode::Ieee => lhs.powf(rhs),
+        Operator::Power => float_power(lhs, rhs)?,
+        _ => return Err(ErrorKind::BitwiseNeedsInteger),
+    };
+    mode.check(result)
+}
+
+/// `lhs ^ rhs` in `f64`, rejecting the cases that have no real result.
+fn float_power(lhs: f64, rhs: f64) -> Result<f64, ErrorKind> {
+    if lhs == 0.0 && rhs < 0.0 {
+        return Err(ErrorKind::DivideByZero);
+    }
+    if lhs < 0.0 && rhs.fract() != 0.0 {
+        return Err(ErrorKind::FractionalPowerOfNegative);
+    }
+    Ok(lhs.powf(rhs))
+}
+
+/// Whether raising a base written with `size` digits to `exponent` stays
+/// within [`MAX_POWER_DIGITS`].
+fn power_fits(size: f64, exponent: i64) -> bool {
+    size * exponent.unsigned_abs() as f64 <= MAX_POWER_DIGITS as f64
+}
+
+/// The digits of `value` written out in full, or 0 for `0` and `±1`,
+/// whose powers never grow.
+fn decimal_power_size(value: &BigDecimal) -> f64 {
+    if value.is_zero() || value.abs().is_one() {
+        return 0.0;
+    }
+    let value = value.normalized();
+    let (_, scale) = value.as_bigint_and_exponent();
+    (value.digits() + scale.unsigned_abs()) as f64
+}
+
+/// Roughly the digits of the numerator and denominator of `value`, or 0
+/// for `0` and `±1`.
+fn rational_power_size(value: &BigRational) -> f64 {
+    if value.is_zero() || value.abs().is_one() {
+        return 0.0;
+    }
+    (value.numer().bits() + value.denom().bits() - 1) as f64 * std::f64::consts::LOG10_2
+}
+
+/// [`float_power`] for the exact backends, which cannot hold infinities.
+fn approximate_power(lhs: f64, rhs: f64) -> Result<BigDecimal, ErrorKind> {
+    let result = float_power(lhs, rhs)?;
+    if !result.is_finite() {
+        return Err(ErrorKind::OutOfRange);
+    }
+    BigDecimal::from_str(&result.to_string()).map_err(|_| ErrorKind::OutOfRange)
+}
+
+fn apply_decimal(
+    lhs: BigDecimal,
+    rhs: BigDecimal,
+    operator: Operator,
+) -> Result<BigDecimal, ErrorKind> {
+    let divides = matches!(
+        operator,
+        Operator::Divide | Operator::Modulo | Operator::FloorDivide
+    );
+    if divides && rhs.is_zero() {
+        return Err(ErrorKind::DivideByZero);
+    }
+
+    let floor_quotient =
+        |lhs: &BigDecimal, rhs: &BigDecimal| (lhs / rhs).with_scale_round(0, RoundingMode::Floor);
+    match operator {
+        Operator::Add => Ok(lhs + rhs),
+        Operator::Subtract => Ok(lhs - rhs),
+        Operator::Multiply => Ok(lhs * rhs),
+        Operator::Divide => Ok(lhs / rhs),
+        Operator::Modulo => {
+            let quotient = floor_quotient(&lhs, &rhs);
+            Ok(lhs - rhs * quotient)
+        }
+        Operator::FloorDivide => Ok(floor_quotient(&lhs, &rhs)),
+        Operator::Power => match rhs.is_integer().then(|| rhs.to_i64()).flatten() {
+            Some(exponent) if lhs.is_zero() && exponent < 0 => Err(ErrorKind::DivideByZero),
+            Some(exponent) if !power_fits(decimal_power_size(&lhs), exponent) => {
+                Err(ErrorKind::OutOfRange)
+            }
+            Some(exponent) => Ok(lhs.powi(exponent)),
+            None => approximate_power(
+                lhs.to_f64().unwrap_or(f64::NAN),
+                rhs.to_f64().unwrap_or(f64::NAN),
+            ),
+        },
+        _ => Err(ErrorKind::BitwiseNeedsInteger),
+    }
+}
+
+fn apply_rational(
+    lhs: BigRational,
+    rhs: BigRational,
+    operator: Operator,
+) -> Result<BigRational, ErrorKind> {
+    let divides = matches!(
+        operator,
+        Operator::Divide | Operator::Modulo | Operator::FloorDivide
+    );
+    if divides && rhs.is_zero() {
+        return Err(ErrorKind::DivideByZero);
+    }
+
+    match operator {
+        Operator::Add => Ok(lhs + rhs),
+        Operator::Subtract => Ok(lhs - rhs),
+        Operator::Multiply => Ok(lhs * rhs),
+        Operator::Divide => Ok(lhs / rhs),
+        Operator::Modulo => {
+            let quotient = (&lhs / &rhs).floor();
+            Ok(lhs - rhs * quotient)
+        }
+        Operator::FloorDivide => Ok((lhs / rhs).floor()),
+        Operator::Power => match rhs
+            .is_integer()
+            .then(|| rhs.to_integer().to_i32())
+            .flatten()
+        {
+            Some(exponent) if lhs.is_zero() && exponent < 0 => Err(ErrorKind::DivideByZero),
+            Some(exponent) if !power_fits(rational_power_size(&lhs), exponent.into()) => {
+                Err(ErrorKind::OutOfRange)
+            }
+            Some(exponent) => Ok(lhs.pow(exponent)),
+            None => approximate_power(
+                lhs.to_f64().unwrap_or(f64::NAN),
+                rhs.to_f64().unwrap_or(f64::NAN),
+            )
+            .map(|value| decimal_to_rational(&value)),
+        },
+        _ => Err(ErrorKind::BitwiseNeedsInteger),
+    }
+}
+
+/// The shortest text that reads back as the same value, without trailing
+/// zeros: `2.50` prints as `2.5`, `3.0` as `3`. Rationals print as a reduced
+/// fraction such as `7/3`, or as an integer when the denominator is 1.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut output = match self {
+            Value::Float(value) if value.is_infinite() => {
+                return f.write_str(if *value < 0.0 { "-∞" } else { "∞" });
+            }
+            Value::Float(value) => format!("{}", value),
+            Value::Decimal(value) => value.normalized().to_plain_string(),
+            Value::Rational(value) if value.is_integer() => return write!(f, "{}", value.numer()),
+            Value::Rational(value) => return write!(f, "{}/{}", value.numer(), value.denom()),
+            Value::Integer(value) => return write!(f, "{value}"),
+        };
+        if output.contains('.') {
+            while output.ends_with('0') {
+                output.pop();
+            }
+            if output.ends_with('.') {
+                output.pop();
+            }
+        }
+        if output.is_empty() || output == "-0" {
+            output = "0".into();
+        }
+        f.write_str(&output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(text: &str) -> Value {
+        Value::parse(text, Backend::Decimal).unwrap()
+    }
+
+    #[test]
+    fn decimal_sums_do_not_drift() {
+        let settings = DecimalSettings::default();
+        let float = Value::Float(0.1)
+            .apply(
+                Operator::Add,
+                Value::Float(0.2),
+                &settings,
+                FloatMode::Checked,
+            )
+            .unwrap();
+        assert_eq!(float.to_string(), "0.30000000000000004");
+
+        let sum = decimal("0.1")
+            .apply(Operator::Add, decimal("0.2"), &settings, FloatMode::Checked)
+            .unwrap();
+        assert_eq!(sum.to_string(), "0.3");
+    }
+
+    #[test]
+    fn decimal_rounds_to_precision_with_mode() {
+        let mut settings = DecimalSettings {
+            precision: 5,
+            rounding: RoundingMode::HalfUp,
+        };
+        let third = decimal("2")
+            .apply(
+                Operator::Divide,
+                decimal("3"),
+                &settings,
+                FloatMode::Checked,
+            )
+            .unwrap();
+        assert_eq!(third.to_string(), "0.66667");
+
+        settings.rounding = RoundingMode::Down;
+        let third = decimal("2")
+            .apply(
+                Operator::Divide,
+                decimal("3"),
+                &settings,
+                FloatMode::Checked,
+            )
+            .unwrap();
+        assert_eq!(third.to_string(), "0.66666");
+
+        let cents = decimal("2.675")
+            .apply(
+                Operator::Multiply,
+                decimal("1"),
+                &DecimalSettings {
+                    precision: 3,
+                    rounding: RoundingMode::HalfEven,
+                },
+                FloatMode::Checked,
+            )
+            .unwrap();
+        assert_eq!(cents.to_string(), "2.68");
+    }
+
+    #[test]
+    fn decimal_division_by_zero_is_an_error() {
+        let settings = DecimalSettings::default();
+        assert_eq!(
+            decimal("1").apply(
+                Operator::Divide,
+                decimal("0.00"),
+                &settings,
+                FloatMode::Checked
+            ),
+            Err(ErrorKind::DivideByZero)
+        );
+    }
+
+    fn rational(text: &str) -> Value {
+        Value::parse(text, Backend::Rational).unwrap()
+    }
+
+    #[test]
+    fn rational_arithmetic_is_exact() {
+        let settings = DecimalSettings::default();
+        let third = rational("1")
+            .apply(
+                Operator::Divide,
+                rational("3"),
+                &settings,
+                FloatMode::Checked,
+            )
+            .unwrap();
+        assert_eq!(third.to_string(), "1/3");
+
+        let one = third
+            .apply(
+                Operator::Multiply,
+                rational("3"),
+                &settings,
+                FloatMode::Checked,
+            )
+            .unwrap();
+        assert_eq!(one.to_string(), "1");
+
+        let sum = rational("0.1")
+            .apply(
+                Operator::Add,
+                rational("0.2"),
+                &settings,
+                FloatMode::Checked,
+            )
+            .unwrap();
+        assert_eq!(sum.to_string(), "3/10");
+        assert_eq!(
+            rational("1").apply(
+                Operator::Divide,
+                rational("0"),
+                &settings,
+                FloatMode::Checked
+            ),
+            Err(ErrorKind::DivideByZero)
+        );
+    }
+
+    #[test]
+    fn rational_formats_as_fraction_mixed_or_decimal() {
+        let settings = DecimalSettings::default();
+        let plain = NumberFormat::default();
+        let value = rational("7/3");
+        assert_eq!(
+            value.format(FractionStyle::Improper, &settings, &plain),
+            "7/3"
+        );
+        assert_eq!(
+            value.format(FractionStyle::Mixed, &settings, &plain),
+            "2 1/3"
+        );
+        assert_eq!(
+            value.format(FractionStyle::Decimal, &settings, &plain),
+            "2.333333333333333333333333333333333"
+        );
+
+        let negative = rational("-7/3");
+        assert_eq!(
+            negative.format(FractionStyle::Mixed, &settings, &plain),
+            "-2 1/3"
+        );
+        assert_eq!(
+            rational("2/3").format(FractionStyle::Mixed, &settings, &plain),
+            "2/3"
+        );
+        let fixed = NumberFormat {
+            decimals: Some(2),
+            grouping: true,
+            ..plain
+        };
+        assert_eq!(
+            rational("10000/3").format(FractionStyle::Decimal, &settings, &fixed),
+            "3,333.33"
+        );
+        assert_eq!(
+            rational("6/3").format(FractionStyle::Mixed, &settings, &plain),
+            "2"
+        );
+    }
+
+    #[test]
+    fn fractions_parse_in_every_backend() {
+        assert_eq!(Value::parse("7/2", Backend::Float), Ok(Value::Float(3.5)));
+        assert_eq!(decimal("7/2").to_string(), "3.5");
+        assert_eq!(rational("2.5").to_string(), "5/2");
+        assert!(Value::parse("1/0", Backend::Rational).is_err());
+    }
+
+    #[test]
+    fn conversions_between_backends_keep_decimal_text() {
+        assert_eq!(
+            Value::Float(0.1).into_backend(Backend::Rational),
+            rational("1/10")
+        );
+        assert_eq!(
+            rational("1/4").into_backend(Backend::Decimal),
+            decimal("0.25")
+        );
+        assert_eq!(
+            rational("1/4").into_backend(Backend::Float),
+            Value::Float(0.25)
+        );
+    }
+
+    #[test]
+    fn functions_check_their_domain() {
+        let settings = DecimalSettings::default();
+        let apply = |text: &str, backend, function| {
+            Value::parse(text, backend).unwrap().apply_function(
+                function,
+                &settings,
+                AngleUnit::Radians,
+                FloatMode::Checked,
+            )
+        };
+
+        assert_eq!(
+            apply("2", Backend::Float, Function::Sqrt),
+            Ok(Value::Float(2f64.sqrt()))
+        );
+        assert_eq!(
+            apply("-1", Backend::Float, Function::Sqrt),
+            Err(ErrorKind::SquareRootOfNegative)
+        );
+        assert_eq!(
+            apply("0", Backend::Decimal, Function::Ln),
+            Err(ErrorKind::LogarithmOfZero)
+        );
+        assert_eq!(
+            apply("-10", Backend::Float, Function::Log),
+            Err(ErrorKind::LogarithmOfNegative)
+        );
+        let half_pi = std::f64::consts::FRAC_PI_2.to_string();
+        assert!(apply(&half_pi, Backend::Float, Function::Tan).is_err());
+    }
+
+    #[test]
+    fn trig_follows_the_angle_unit() {
+        let settings = DecimalSettings::default();
+        let apply = |x: f64, function, angle| {
+            Value::Float(x)
+                .apply_function(function, &settings, angle, FloatMode::Checked)
+                .map(|value| value.to_f64())
+        };
+
+        assert_eq!(apply(180.0, Function::Sin, AngleUnit::Degrees), Ok(0.0));
+        assert_eq!(apply(-90.0, Function::Sin, AngleUnit::Degrees), Ok(-1.0));
+        assert_eq!(apply(200.0, Function::Cos, AngleUnit::Gradians), Ok(-1.0));
+        assert_eq!(apply(30.0, Function::Sin, AngleUnit::Degrees), Ok(0.5));
+        assert_eq!(apply(60.0, Function::Cos, AngleUnit::Degrees), Ok(0.5));
+        assert!(apply(90.0, Function::Tan, AngleUnit::Degrees).is_err());
+        assert!(apply(300.0, Function::Tan, AngleUnit::Gradians).is_err());
+        assert_eq!(apply(45.0, Function::Tan, AngleUnit::Degrees), Ok(1.0));
+        assert_eq!(apply(50.0, Function::Tan, AngleUnit::Gradians), Ok(1.0));
+
+        assert_eq!(apply(1.0, Function::Asin, AngleUnit::Degrees), Ok(90.0));
+        assert_eq!(apply(-1.0, Function::Acos, AngleUnit::Gradians), Ok(200.0));
+        assert_eq!(apply(1.0, Function::Atan, AngleUnit::Degrees), Ok(45.0));
+        assert_eq!(apply(0.5, Function::Asin, AngleUnit::Degrees), Ok(30.0));
+        assert_eq!(apply(0.5, Function::Acos, AngleUnit::Degrees), Ok(60.0));
+        assert_eq!(apply(1.0, Function::Acos, AngleUnit::Radians), Ok(0.0));
+        assert_eq!(
+            apply(2.0, Function::Asin, AngleUnit::Degrees),
+            Err(ErrorKind::InverseSineOutOfDomain)
+        );
+    }
+
+    #[test]
+    fn square_roots_stay_exact_where_possible() {
+        let settings = DecimalSettings::default();
+        assert_eq!(
+            rational("9/4").apply_function(
+                Function::Sqrt,
+                &settings,
+                AngleUnit::Radians,
+                FloatMode::Checked
+            ),
+            Ok(rational("3/2"))
+        );
+        assert_eq!(
+            decimal("2")
+                .apply_function(
+                    Function::Sqrt,
+                    &settings,
+                    AngleUnit::Radians,
+                    FloatMode::Checked
+                )
+                .unwrap()
+                .to_string(),
+            "1.414213562373095048801688724209698"
+        );
+        assert_eq!(
+            rational("1000")
+                .apply_function(
+                    Function::Log,
+                    &settings,
+                    AngleUnit::Radians,
+                    FloatMode::Checked
+                )
+                .unwrap(),
+            rational("3")
+        );
+    }
+
+    #[test]
+    fn power_modulo_and_floor_division_in_every_backend() {
+        let settings = DecimalSettings::default();
+        let cases = [
+            ("2", Operator::Power, "10", "1024"),
+            ("2", Operator::Power, "-2", "0.25"),
+            ("9", Operator::Power, "0.5", "3"),
+            ("7", Operator::Modulo, "3", "1"),
+            ("-7", Operator::Modulo, "3", "2"),
+            ("7", Operator::Modulo, "-3", "-2"),
+            ("7", Operator::FloorDivide, "2", "3"),
+            ("-7", Operator::FloorDivide, "2", "-4"),
+        ];
+        for backend in [Backend::Float, Backend::Decimal, Backend::Rational] {
+            for (lhs, operator, rhs, expected) in cases {
+                let lhs = Value::parse(lhs, backend).unwrap();
+                let rhs = Value::parse(rhs, backend).unwrap();
+                let result = lhs
+                    .apply(operator, rhs, &settings, FloatMode::Checked)
+                    .unwrap();
+                assert_eq!(
+                    result,
+                    Value::parse(expected, backend).unwrap(),
+                    "{backend:?} {operator:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn power_and_modulo_errors() {
+        let settings = DecimalSettings::default();
+        for backend in [Backend::Float, Backend::Decimal, Backend::Rational] {
+            let value = |text| Value::parse(text, backend).unwrap();
+            assert_eq!(
+                value("0").apply(Operator::Power, value("-1"), &settings, FloatMode::Checked),
+                Err(ErrorKind::DivideByZero)
+            );
+            assert_eq!(
+                value("-8").apply(Operator::Power, value("0.5"), &settings, FloatMode::Checked),
+                Err(ErrorKind::FractionalPowerOfNegative)
+            );
+            assert_eq!(
+                value("5").apply(Operator::Modulo, value("0"), &settings, FloatMode::Checked),
+                Err(ErrorKind::DivideByZero)
+            );
+        }
+    }
+
+    #[test]
+    fn exact_powers_are_bounded() {
+        let settings = DecimalSettings::default();
+        let cases = [
+            ("10", "999999999", Err(ErrorKind::OutOfRange)),
+            ("10", "-999999999", Err(ErrorKind::OutOfRange)),
+            ("0.5", "2147483647", Err(ErrorKind::OutOfRange)),
+            ("1.5", "-100000", Err(ErrorKind::OutOfRange)),
+            ("1", "999999999", Ok("1")),
+            ("-1", "-999999999", Ok("-1")),
+            ("10", "300", Ok("1e300")),
+        ];
+        for backend in [Backend::Decimal, Backend::Rational] {
+            let value = |text| Value::parse(text, backend).unwrap();
+            for (lhs, rhs, expected) in cases {
+                assert_eq!(
+                    value(lhs).apply(Operator::Power, value(rhs), &settings, FloatMode::Checked),
+                    expected.map(value),
+                    "{backend:?} {lhs} ^ {rhs}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rounding_mode_names_round_trip() {
+        for mode in ROUNDING_MODES {
+            assert_eq!(parse_rounding_mode(rounding_mode_name(mode)), Some(mode));
+        }
+        assert_eq!(parse_rounding_mode("sideways"), None);
+    }
+
+    #[test]
+    fn float_overflow_and_nan_follow_the_float_mode() {
+        let settings = DecimalSettings::default();
+        let apply = |lhs: f64, operator, rhs: f64, mode| {
+            Value::Float(lhs).apply(operator, Value::Float(rhs), &settings, mode)
+        };
+        let checked = FloatMode::Checked;
+        assert_eq!(
+            apply(1e308, Operator::Multiply, 10.0, checked),
+            Err(ErrorKind::Overflow)
+        );
+        assert_eq!(
+            apply(1.0, Operator::Divide, 1e-20, checked),
+            Ok(Value::Float(1e20))
+        );
+        assert_eq!(
+            apply(1.0, Operator::Divide, 0.0, checked),
+            Err(ErrorKind::DivideByZero)
+        );
+        assert_eq!(
+            apply(f64::INFINITY, Operator::Subtract, f64::INFINITY, checked),
+            Err(ErrorKind::Undefined)
+        );
+        assert_eq!(
+            Value::Float(1000.0).apply_function(
+                Function::Exp,
+                &settings,
+                AngleUnit::Radians,
+                checked
+            ),
+            Err(ErrorKind::Overflow)
+        );
+
+        let ieee = |result: Result<Value, ErrorKind>| result.unwrap().to_string();
+        let mode = FloatMode::Ieee;
+        assert_eq!(ieee(apply(1e308, Operator::Multiply, 10.0, mode)), "∞");
+        assert_eq!(ieee(apply(-1.0, Operator::Divide, 0.0, mode)), "-∞");
+        assert_eq!(ieee(apply(0.0, Operator::Divide, 0.0, mode)), "NaN");
+        assert_eq!(
+            ieee(Value::Float(-1.0).apply_function(
+                Function::Sqrt,
+                &settings,
+                AngleUnit::Radians,
+                mode
+            )),
+            "NaN"
+        );
+        assert_eq!(
+            Value::parse("-∞", Backend::Float),
+            Ok(Value::Float(f64::NEG_INFINITY))
+        );
+    }
+}